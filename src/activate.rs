@@ -0,0 +1,62 @@
+//! Handling `_NET_ACTIVE_WINDOW` requests, which is how apps ask to be raised,
+//! e.g. a browser when a link is opened from somewhere else.
+//!
+//! Apps asking for themselves don't get to steal focus, since that's just as
+//! likely to happen mid-sentence in another window. Only requests made on the
+//! user's behalf by a pager or taskbar are honoured, and anything else is
+//! marked urgent instead so it can be jumped to with `M-u`.
+use crate::urgent::set_urgent;
+use penrose::{
+    core::State,
+    x::{
+        atom::Atom,
+        event::{ClientMessageData, XEvent},
+        XConn, XConnExt,
+    },
+    Result,
+};
+use tracing::info;
+
+/// `_NET_ACTIVE_WINDOW` source indication for pagers and other tools acting
+/// directly for the user, from the EWMH spec.
+const SOURCE_PAGER: u32 = 2;
+
+/// Event hook deciding what to do with `_NET_ACTIVE_WINDOW` requests.
+pub fn active_window_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    let (client, source) = match event {
+        XEvent::ClientMessage(message) if message.dtype == Atom::NetActiveWindow.as_ref() => {
+            match message.data {
+                ClientMessageData::U32(data) => (message.id, data[0]),
+                _ => return Ok(true),
+            }
+        }
+        _ => return Ok(true),
+    };
+    if !state.client_set.contains(&client) || state.client_set.current_client() == Some(&client) {
+        return Ok(false);
+    }
+
+    if source != SOURCE_PAGER {
+        info!(
+            ?client,
+            source, "Not letting a client steal focus, marking it urgent"
+        );
+        if set_urgent(client, true, state) {
+            x.refresh(state)?;
+        }
+        return Ok(false);
+    }
+
+    info!(?client, "Activating client on request");
+    if let Some(tag) = state.client_set.tag_for_client(&client).map(String::from) {
+        state.client_set.focus_tag(&tag);
+    }
+    state.client_set.focus_client(&client);
+    x.refresh(state)?;
+
+    Ok(false)
+}
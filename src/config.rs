@@ -0,0 +1,850 @@
+//! Runtime configuration read from `$XDG_CONFIG_HOME/wendy/wendy.toml`.
+//!
+//! Pinned apps are listed as an array of `[[app]]` tables:
+//!
+//! ```toml
+//! [[app]]
+//! tag = "1"
+//! command = "emacs"
+//! match = { app_name = "emacs" }
+//!
+//! [[app]]
+//! tag = "3"
+//! command = "chromium"
+//! match = { class_regex = "^[Cc]hromium" }
+//!
+//! [[app]]
+//! tag = "4"
+//! command = "DiscordCanary"
+//! match = [{ app_name = "DiscordCanary" }, { app_name = "discord" }]
+//! ```
+//!
+//! Apps can also be given extra environment variables and a working directory
+//! to start in:
+//!
+//! ```toml
+//! [[app]]
+//! tag = "2"
+//! command = "alacritty"
+//! match = { app_name = "Alacritty" }
+//! cwd = "~/work"
+//! env = { TERM = "xterm-256color" }
+//! ```
+//!
+//! An app can span several tags, with each of its windows going to the lowest
+//! numbered tag whose `match` it also matches. Each tag can have its own `command` to
+//! launch with, and windows matching none of them are placed like any other:
+//!
+//! ```toml
+//! [[app]]
+//! command = "chromium"
+//! match = { class_regex = "^[Cc]hromium" }
+//!
+//! [[app.tags]]
+//! tag = "3"
+//! match = { title_contains = "(Work)" }
+//!
+//! [[app.tags]]
+//! tag = "6"
+//! match = { title_contains = "(Personal)" }
+//! command = "chromium --profile-directory=Personal"
+//! ```
+//!
+//! If an app's window has been moved off its tag, `M-{tag}` goes to wherever
+//! it is now. Setting `running_elsewhere = "move"` brings it back instead.
+//!
+//! Giving an app a `border` colour (e.g. `border = "#98971a"`) uses it in place
+//! of `focused_border` while one of its windows is focused.
+//!
+//! Once `max_clients` windows are on an app's tag, any more go to a tag of
+//! their own. This can be set per app, or for everything under `[placement]`:
+//!
+//! ```toml
+//! [placement]
+//! max_clients = 6
+//! ```
+//!
+//! New windows go to the front of the alt-tab list, unless
+//! `new_window_to_front = false` is set under `[placement]`, in which case
+//! they go to the back until they're focused.
+//!
+//! A `match` table can use `app_name`, `class_name`, `class_regex`,
+//! `title_contains` or `pid_exe` (the executable of the window's process, for
+//! apps that don't set a useful class). Titles are only checked when a window
+//! first appears.
+//! Where a list matches if any of its tables do, `all` only matches if every
+//! one of them does:
+//!
+//! ```toml
+//! [[app]]
+//! tag = "6"
+//! command = "slack"
+//! match = { all = [{ class_name = "Electron" }, { title_contains = "Slack" }] }
+//! ```
+//!
+//! Terminals opened with `M-Return` start in the focused tag's directory, if
+//! it has one:
+//!
+//! ```toml
+//! [directories]
+//! "3" = "~/src/wendy"
+//! ```
+//!
+//! Dialogs and utility windows float on their own. Other apps can be floated
+//! (or dialogs tiled) with a `[[rule]]`, which wins over the window type:
+//!
+//! ```toml
+//! [[rule]]
+//! match = { class_name = "Gnome-calculator" }
+//! float = true
+//! ```
+//!
+//! In a tiled layout, a window matching `[main]` is kept in the main area with
+//! everything else stacked beside it, whatever order they were opened in:
+//!
+//! ```toml
+//! [main]
+//! match = { app_name = "emacs" }
+//! ```
+//!
+//! Tags can be put on particular monitors at startup, by RandR output name:
+//!
+//! ```toml
+//! [outputs]
+//! "DP-1" = "1"
+//! "HDMI-1" = "3"
+//! ```
+//!
+//! Tags can also be given a starting layout other than Monocle:
+//!
+//! ```toml
+//! [layouts]
+//! "3" = "main_and_stack"
+//! ```
+//!
+//! Monocle workspaces can switch to another layout once more than `threshold`
+//! windows are on them, and back again when some are closed. The layout can be
+//! `main_and_stack` (the default) or `grid`:
+//!
+//! ```toml
+//! [overflow]
+//! threshold = 4
+//! layout = "grid"
+//! ```
+//!
+//! The screen locker defaults to xscreensaver, but can be swapped out or
+//! disabled by leaving out its commands:
+//!
+//! ```toml
+//! [lock]
+//! command = "xsecurelock"
+//! idle_minutes = 10
+//! ```
+//!
+//! With `idle_minutes` set, the lock command is also run after that long
+//! without any input.
+//!
+//! `M-A-Escape` has to be pressed twice in a row to exit, unless told not to
+//! bother asking:
+//!
+//! ```toml
+//! [exit]
+//! confirm = false
+//! ```
+//!
+//! Anything else to launch along with the WM goes under `[[autostart]]`. An
+//! entry with a `process` isn't launched again if a process by that name is
+//! already running, e.g. after restarting the WM:
+//!
+//! ```toml
+//! [[autostart]]
+//! command = "picom --daemon"
+//! process = "picom"
+//!
+//! [[autostart]]
+//! command = "nm-applet"
+//! ```
+//!
+//! GUI apps launched from a terminal swallow it, hiding the terminal until
+//! they're closed. Which processes count as terminals can be changed, and an
+//! empty list turns swallowing off:
+//!
+//! ```toml
+//! [swallow]
+//! terminals = ["alacritty", "kitty"]
+//! ```
+//!
+//! Gaps and borders all have defaults, but can be overridden individually:
+//!
+//! ```toml
+//! [appearance]
+//! border_width = 2
+//! outer_gap = 8
+//! inner_gap = 8
+//! focused_border = "#458588"
+//! normal_border = "#3c3836"
+//! ```
+//!
+//! Setting `inactive_opacity` (a percentage) under `[appearance]` dims
+//! unfocused windows through `_NET_WM_WINDOW_OPACITY`, for a compositor to
+//! pick up. It's off by default.
+//!
+//! `M-S-g` turns the gaps off on the focused workspace, or on all of them with
+//! `toggle_gaps_everywhere = true` under `[appearance]`.
+//!
+//! Alt-tab wraps around at the ends of the list unless told otherwise, and can
+//! be moved to Super (which takes over Super+grave from the scratchpad):
+//!
+//! ```toml
+//! [switching]
+//! wrap = false
+//! modifier = "super"
+//! ```
+//!
+//! Switching within the focused workspace (grave) or app (backslash) follows
+//! `modifier` too, unless given its own with `workspace_modifier` or
+//! `same_app_modifier`, e.g. to keep `workspace_modifier = "alt"` while Tab
+//! moves to Super.
+use crate::{
+    default_pinned_apps, default_tag_layouts,
+    queries::{ClassNameRegex, PidExe, TitleContains},
+    PinnedApp, SwitchContext, TAGS,
+};
+use penrose::{
+    core::bindings::ModifierKey,
+    x::{
+        query::{self, Query},
+        XConn,
+    },
+    Color, Error, Result, Xid,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+use x11rb::protocol::xproto::ModMask;
+
+pub fn config_path() -> PathBuf {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    config_home.join("wendy").join("wendy.toml")
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default, rename = "app")]
+    apps: Vec<PinnedAppEntry>,
+    #[serde(default)]
+    layouts: HashMap<String, LayoutKind>,
+    #[serde(default)]
+    directories: HashMap<String, PathBuf>,
+    #[serde(default)]
+    outputs: HashMap<String, String>,
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleEntry>,
+    lock: Option<LockConfig>,
+    #[serde(default)]
+    exit: ExitConfig,
+    #[serde(default)]
+    appearance: Appearance,
+    #[serde(default)]
+    switching: SwitchConfig,
+    #[serde(default)]
+    placement: PlacementConfig,
+    main: Option<MainEntry>,
+    #[serde(default)]
+    autostart: Vec<AutostartEntry>,
+    #[serde(default)]
+    swallow: SwallowConfig,
+    #[serde(default)]
+    overflow: OverflowConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct PinnedAppEntry {
+    /// Left out for apps spanning several `tags`
+    tag: Option<String>,
+    #[serde(default)]
+    tags: Vec<TagBindingEntry>,
+    command: String,
+    #[serde(rename = "match")]
+    queries: AppMatches,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    cwd: Option<PathBuf>,
+    max_clients: Option<usize>,
+    #[serde(default)]
+    running_elsewhere: RunningElsewhere,
+    /// Border colour for the app's windows while they're focused
+    border: Option<String>,
+}
+
+/// One of the tags an app spans, and which of its windows go there.
+#[derive(Debug, Deserialize)]
+struct TagBindingEntry {
+    tag: String,
+    #[serde(rename = "match")]
+    queries: AppMatches,
+    /// What to launch for this tag, rather than the app's usual command
+    command: Option<String>,
+}
+
+/// What `M-{tag}` does when a pinned app is running, but not on its tag.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunningElsewhere {
+    /// Go to wherever it is
+    #[default]
+    Focus,
+    /// Bring it back to its tag
+    Move,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleEntry {
+    #[serde(rename = "match")]
+    queries: AppMatches,
+    #[serde(default = "default_float")]
+    float: bool,
+}
+
+fn default_float() -> bool {
+    true
+}
+
+/// Whether clients matching any of `queries` should float, regardless of what
+/// their window type says.
+#[derive(Debug)]
+pub struct FloatRule<X: XConn> {
+    pub queries: Vec<Box<dyn Query<X>>>,
+    pub float: bool,
+}
+
+pub fn load_float_rules<X: XConn>(path: &Path) -> Result<Vec<FloatRule<X>>> {
+    Ok(read_config_file(path)?
+        .map(|file| {
+            file.rules
+                .into_iter()
+                .map(|rule| FloatRule {
+                    queries: rule.queries.into_queries(),
+                    float: rule.float,
+                })
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+#[derive(Debug, Deserialize)]
+struct MainEntry {
+    #[serde(rename = "match")]
+    queries: AppMatches,
+}
+
+/// Read the queries for the app to keep in the main area from `path`. Empty
+/// if there isn't one.
+pub fn load_main_app<X: XConn>(path: &Path) -> Result<Vec<Box<dyn Query<X>>>> {
+    Ok(read_config_file(path)?
+        .and_then(|file| file.main)
+        .map(|main| main.queries.into_queries())
+        .unwrap_or_default())
+}
+
+/// A `match` can either be a single table or a list of them, in which case the
+/// app matches if any of them do.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum AppMatches {
+    One(AppMatch),
+    Many(Vec<AppMatch>),
+}
+
+impl AppMatches {
+    fn into_queries<X: XConn>(self) -> Vec<Box<dyn Query<X>>> {
+        let matches = match self {
+            AppMatches::One(app_match) => vec![app_match],
+            AppMatches::Many(matches) => matches,
+        };
+        matches
+            .into_iter()
+            .map(|app_match| Box::new(app_match) as Box<dyn Query<X>>)
+            .collect()
+    }
+}
+
+/// An owned version of penrose's string queries so they can be built from
+/// the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppMatch {
+    AppName(String),
+    ClassName(String),
+    ClassRegex(ClassNameRegex),
+    TitleContains(String),
+    PidExe(String),
+    /// Matches only if all of these do, e.g. a generic class along with a
+    /// distinguishing title. One of them failing (say, a property that couldn't
+    /// be read) fails the whole thing rather than being skipped over.
+    All(Vec<AppMatch>),
+}
+
+impl<X: XConn> Query<X> for AppMatch {
+    fn run(&self, id: Xid, x: &X) -> Result<bool> {
+        match self {
+            AppMatch::AppName(name) => query::AppName(name).run(id, x),
+            AppMatch::ClassName(name) => query::ClassName(name).run(id, x),
+            AppMatch::ClassRegex(regex) => regex.run(id, x),
+            AppMatch::TitleContains(title) => TitleContains(title).run(id, x),
+            AppMatch::PidExe(exe) => PidExe(exe).run(id, x),
+            AppMatch::All(matches) => {
+                for app_match in matches {
+                    if !Query::<X>::run(app_match, id, x)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+/// The layouts a tag can start out with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutKind {
+    Monocle,
+    MainAndStack,
+    Grid,
+}
+
+/// Parse the config file at `path`, or `None` if it doesn't exist.
+fn read_config_file(path: &Path) -> Result<Option<ConfigFile>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(Error::Custom(format!(
+                "unable to read {}: {e}",
+                path.display()
+            )))
+        }
+    };
+    toml::from_str(&contents)
+        .map(Some)
+        .map_err(|e| Error::Custom(format!("unable to parse {}: {e}", path.display())))
+}
+
+/// Read which tag goes on each output from `path`.
+pub fn load_output_tags(path: &Path) -> Result<HashMap<String, String>> {
+    Ok(read_config_file(path)?
+        .map(|file| file.outputs)
+        .unwrap_or_default())
+}
+
+/// Read the directory terminals should start in for each tag from `path`.
+pub fn load_tag_directories(path: &Path) -> Result<HashMap<String, PathBuf>> {
+    Ok(read_config_file(path)?
+        .map(|file| {
+            file.directories
+                .into_iter()
+                .map(|(tag, dir)| (tag, expand_home(&dir)))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Read the per-tag layouts from `path`. Tags in the file override the
+/// compiled in defaults.
+pub fn load_tag_layouts(path: &Path) -> Result<HashMap<String, LayoutKind>> {
+    let mut layouts = default_tag_layouts();
+    if let Some(file) = read_config_file(path)? {
+        layouts.extend(file.layouts);
+    }
+
+    Ok(layouts)
+}
+
+/// Gaps and borders, in pixels. Colours are `#rrggbb` hex strings.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Appearance {
+    pub border_width: u32,
+    pub outer_gap: u32,
+    pub inner_gap: u32,
+    pub focused_border: String,
+    pub normal_border: String,
+    /// How opaque unfocused windows are, as a percentage
+    pub inactive_opacity: Option<u8>,
+    /// Whether `M-S-g` turns gaps off on every workspace, rather than just the
+    /// focused one
+    pub toggle_gaps_everywhere: bool,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            border_width: 2,
+            outer_gap: 8,
+            inner_gap: 8,
+            focused_border: "#458588".to_string(),
+            normal_border: "#3c3836".to_string(),
+            inactive_opacity: None,
+            toggle_gaps_everywhere: false,
+        }
+    }
+}
+
+/// Read the gap and border settings from `path`, using the defaults for
+/// anything left out.
+pub fn load_appearance(path: &Path) -> Result<Appearance> {
+    Ok(read_config_file(path)?
+        .map(|file| file.appearance)
+        .unwrap_or_default())
+}
+
+/// A program to launch on startup.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct AutostartEntry {
+    pub command: String,
+    /// The process to look for to tell whether it's already running
+    pub process: Option<String>,
+}
+
+pub fn load_autostart(path: &Path) -> Result<Vec<AutostartEntry>> {
+    Ok(read_config_file(path)?
+        .map(|file| file.autostart)
+        .unwrap_or_default())
+}
+
+/// Where new windows go.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PlacementConfig {
+    /// How many windows a tag can hold before new ones overflow elsewhere, for
+    /// apps that don't set their own
+    pub max_clients: Option<usize>,
+    /// Whether new windows go to the front of the MRU list, rather than the
+    /// back
+    pub new_window_to_front: bool,
+}
+
+impl Default for PlacementConfig {
+    fn default() -> Self {
+        Self {
+            max_clients: None,
+            new_window_to_front: true,
+        }
+    }
+}
+
+pub fn load_placement_config(path: &Path) -> Result<PlacementConfig> {
+    Ok(read_config_file(path)?
+        .map(|file| file.placement)
+        .unwrap_or_default())
+}
+
+/// When crowded Monocle workspaces switch to another layout.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct OverflowConfig {
+    /// How many windows Monocle can hold before switching, or `None` to never
+    /// switch
+    pub threshold: Option<usize>,
+    pub layout: LayoutKind,
+}
+
+impl Default for OverflowConfig {
+    fn default() -> Self {
+        Self {
+            threshold: None,
+            layout: LayoutKind::MainAndStack,
+        }
+    }
+}
+
+pub fn load_overflow_config(path: &Path) -> Result<OverflowConfig> {
+    Ok(read_config_file(path)?
+        .map(|file| file.overflow)
+        .unwrap_or_default())
+}
+
+/// Which terminals get swallowed by the GUI apps they launch.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SwallowConfig {
+    /// Process names of terminals
+    pub terminals: Vec<String>,
+}
+
+impl Default for SwallowConfig {
+    fn default() -> Self {
+        Self {
+            terminals: vec!["alacritty".to_string()],
+        }
+    }
+}
+
+pub fn load_swallow_config(path: &Path) -> Result<SwallowConfig> {
+    Ok(read_config_file(path)?
+        .map(|file| file.swallow)
+        .unwrap_or_default())
+}
+
+/// How alt-tab behaves.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SwitchConfig {
+    /// Whether to wrap around at the ends of the list, rather than stopping
+    pub wrap: bool,
+    /// What to hold down while pressing Tab. Changing this needs a restart.
+    pub modifier: SwitchModifier,
+    /// What to hold down with grave to switch within the focused workspace,
+    /// if not `modifier`
+    pub workspace_modifier: Option<SwitchModifier>,
+    /// What to hold down with backslash to switch within the focused app, if
+    /// not `modifier`
+    pub same_app_modifier: Option<SwitchModifier>,
+}
+
+impl Default for SwitchConfig {
+    fn default() -> Self {
+        Self {
+            wrap: true,
+            modifier: SwitchModifier::Alt,
+            workspace_modifier: None,
+            same_app_modifier: None,
+        }
+    }
+}
+
+impl SwitchConfig {
+    /// The modifier for switching within `context`.
+    pub fn modifier_for(&self, context: &SwitchContext) -> SwitchModifier {
+        match context {
+            SwitchContext::Global => self.modifier,
+            SwitchContext::Workspace => self.workspace_modifier.unwrap_or(self.modifier),
+            SwitchContext::SameApp => self.same_app_modifier.unwrap_or(self.modifier),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchModifier {
+    Alt,
+    Super,
+}
+
+impl SwitchModifier {
+    /// The prefix for this modifier in key binding strings.
+    pub fn binding_prefix(&self) -> &'static str {
+        match self {
+            SwitchModifier::Alt => "A",
+            SwitchModifier::Super => "M",
+        }
+    }
+
+    /// The key itself, so we can see it being released.
+    pub fn keysym(&self) -> &'static str {
+        match self {
+            SwitchModifier::Alt => "Alt_L",
+            SwitchModifier::Super => "Super_L",
+        }
+    }
+
+    pub fn key(&self) -> ModifierKey {
+        match self {
+            SwitchModifier::Alt => ModifierKey::Alt,
+            SwitchModifier::Super => ModifierKey::Meta,
+        }
+    }
+
+    pub fn mod_mask(&self) -> ModMask {
+        match self {
+            SwitchModifier::Alt => ModMask::M1,
+            SwitchModifier::Super => ModMask::M4,
+        }
+    }
+}
+
+pub fn load_switch_config(path: &Path) -> Result<SwitchConfig> {
+    Ok(read_config_file(path)?
+        .map(|file| file.switching)
+        .unwrap_or_default())
+}
+
+/// Whether exiting needs confirming with a second press.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ExitConfig {
+    pub confirm: bool,
+}
+
+impl Default for ExitConfig {
+    fn default() -> Self {
+        Self { confirm: true }
+    }
+}
+
+pub fn load_exit_config(path: &Path) -> Result<ExitConfig> {
+    Ok(read_config_file(path)?
+        .map(|file| file.exit)
+        .unwrap_or_default())
+}
+
+/// The screen locker. `daemon` is started along with the WM and `command` is
+/// run to lock the screen. Either can be left out if the locker doesn't need it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct LockConfig {
+    pub daemon: Option<String>,
+    pub command: Option<String>,
+    pub idle_minutes: Option<u64>,
+}
+
+impl Default for LockConfig {
+    fn default() -> Self {
+        Self {
+            daemon: Some("xscreensaver".to_string()),
+            command: Some("xscreensaver-command --lock".to_string()),
+            idle_minutes: None,
+        }
+    }
+}
+
+/// Read the locker from `path`. `$WENDY_LOCK_DAEMON` and `$WENDY_LOCK_COMMAND`
+/// take precedence over the file, and setting either to an empty string
+/// disables it.
+pub fn load_lock_config(path: &Path) -> Result<LockConfig> {
+    let mut lock = read_config_file(path)?
+        .and_then(|file| file.lock)
+        .unwrap_or_default();
+    if let Some(daemon) = env::var_os("WENDY_LOCK_DAEMON") {
+        lock.daemon = Some(daemon.to_string_lossy().into_owned()).filter(|s| !s.is_empty());
+    }
+    if let Some(command) = env::var_os("WENDY_LOCK_COMMAND") {
+        lock.command = Some(command.to_string_lossy().into_owned()).filter(|s| !s.is_empty());
+    }
+
+    Ok(lock)
+}
+
+/// Tags and commands shared by more than one pinned app, described for the
+/// user. Each tag only has room for the one app, and an app pinned twice
+/// would be fought over.
+pub fn pinned_app_conflicts<'a>(
+    apps: impl IntoIterator<Item = (Vec<&'a str>, &'a str)>,
+) -> Vec<String> {
+    let mut commands_by_tag = BTreeMap::<&str, Vec<&str>>::new();
+    let mut tags_by_command = BTreeMap::<&str, Vec<&str>>::new();
+    for (tags, command) in apps {
+        for tag in &tags {
+            commands_by_tag.entry(tag).or_default().push(command);
+        }
+        // An app spanning several tags only counts once
+        tags_by_command
+            .entry(command)
+            .or_default()
+            .push(tags.first().copied().unwrap_or_default());
+    }
+
+    let tags = commands_by_tag
+        .into_iter()
+        .filter(|(_, commands)| commands.len() > 1)
+        .map(|(tag, commands)| format!("tag {tag:?} is used by {}", commands.join(", ")));
+    let commands = tags_by_command
+        .into_iter()
+        .filter(|(_, tags)| tags.len() > 1)
+        .map(|(command, tags)| format!("`{command}` is pinned to tags {}", tags.join(", ")));
+    tags.chain(commands).collect()
+}
+
+/// Read the pinned apps from `path`, falling back to the compiled in defaults
+/// if the file doesn't exist.
+pub fn load_pinned_apps<X: XConn>(path: &Path) -> Result<HashMap<String, PinnedApp<X>>> {
+    let file = match read_config_file(path)? {
+        Some(file) => file,
+        None => return Ok(default_pinned_apps()),
+    };
+
+    let conflicts = pinned_app_conflicts(file.apps.iter().map(|entry| {
+        let tags = entry
+            .tag
+            .iter()
+            .map(String::as_str)
+            .chain(entry.tags.iter().map(|binding| binding.tag.as_str()))
+            .collect();
+        (tags, entry.command.as_str())
+    }));
+    if !conflicts.is_empty() {
+        return Err(Error::Custom(format!(
+            "conflicting pinned apps: {}",
+            conflicts.join("; ")
+        )));
+    }
+
+    let mut apps = HashMap::new();
+    for entry in file.apps {
+        // Each tag an app spans gets its own copy, narrowed down to the
+        // windows that belong there
+        let border = entry
+            .border
+            .as_deref()
+            .map(Color::try_from)
+            .transpose()
+            .map_err(|e| {
+                Error::Custom(format!(
+                    "pinned app `{}` has an invalid border: {e}",
+                    entry.command
+                ))
+            })?;
+        let bindings = match (entry.tag, entry.tags) {
+            (Some(tag), tags) if tags.is_empty() => vec![(tag, None, None)],
+            (None, tags) if !tags.is_empty() => tags
+                .into_iter()
+                .map(|binding| (binding.tag, Some(binding.queries), binding.command))
+                .collect(),
+            _ => {
+                return Err(Error::Custom(format!(
+                    "pinned app `{}` needs either a `tag` or a list of `tags`",
+                    entry.command
+                )))
+            }
+        };
+        for (tag, tag_queries, command) in bindings {
+            if !TAGS.contains(&tag.as_str()) {
+                return Err(Error::Custom(format!(
+                    "pinned app `{}` uses tag {tag:?}, which isn't one of {TAGS:?}",
+                    entry.command
+                )));
+            }
+            apps.insert(
+                tag,
+                PinnedApp {
+                    command: command.unwrap_or_else(|| entry.command.clone()),
+                    queries: entry.queries.clone().into_queries(),
+                    tag_queries: tag_queries
+                        .map(AppMatches::into_queries)
+                        .unwrap_or_default(),
+                    env: entry.env.clone().into_iter().collect(),
+                    cwd: entry.cwd.as_deref().map(expand_home),
+                    max_clients: entry.max_clients,
+                    running_elsewhere: entry.running_elsewhere,
+                    border,
+                },
+            );
+        }
+    }
+
+    Ok(apps)
+}
+
+/// Expand a leading `~` to `$HOME`.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
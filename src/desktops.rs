@@ -0,0 +1,78 @@
+//! Handling `_NET_WM_DESKTOP` and `_NET_CURRENT_DESKTOP` requests from pagers
+//! and taskbars, to move a window to another tag or switch tags.
+//!
+//! EWMH desktops are numbered from 0, while our tags are strings that come and
+//! go. Desktop `n` is the `n`th tag in `ordered_tags()`, which is the same
+//! order the EWMH hooks (and `_NET_DESKTOP_NAMES`) publish them in, so a pager
+//! always sees the tags as they are now: once `backfill_gaps` has compacted
+//! things or a new tag has been made, the numbering moves with them the next
+//! time they're published. Hidden tags like the scratchpad's still take up a
+//! number, so the rest line up, but requests for them are ignored.
+use crate::{is_hidden_tag, ManualPlacements};
+use penrose::{
+    core::State,
+    x::{
+        atom::Atom,
+        event::{ClientMessageData, XEvent},
+        XConn, XConnExt,
+    },
+    Result,
+};
+use tracing::{debug, info};
+
+/// The tag a pager means by `desktop`, unless it's one of the hidden ones.
+fn tag_for_desktop<X: XConn>(state: &State<X>, desktop: u32) -> Option<String> {
+    state
+        .client_set
+        .ordered_tags()
+        .into_iter()
+        .nth(desktop as usize)
+        .filter(|tag| !is_hidden_tag(tag))
+}
+
+/// Event hook acting on desktop requests from pagers.
+pub fn desktop_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    let (id, dtype, desktop) = match event {
+        XEvent::ClientMessage(message) => match message.data {
+            ClientMessageData::U32(data) => (message.id, message.dtype.as_str(), data[0]),
+            _ => return Ok(true),
+        },
+        _ => return Ok(true),
+    };
+    if dtype != Atom::NetWmDesktop.as_ref() && dtype != Atom::NetCurrentDesktop.as_ref() {
+        return Ok(true);
+    }
+    let tag = match tag_for_desktop(state, desktop) {
+        Some(tag) => tag,
+        None => {
+            debug!(
+                desktop,
+                "Ignoring request for a desktop we don't have or hide"
+            );
+            return Ok(false);
+        }
+    };
+
+    if dtype == Atom::NetCurrentDesktop.as_ref() {
+        info!("Switching to {tag} for a pager");
+        state.client_set.focus_tag(&tag);
+    } else if state.client_set.contains(&id) {
+        info!(client = ?id, "Moving client to {tag} for a pager");
+        // Someone asked for it to go there, so placement shouldn't move it on
+        state
+            .extension_or_default::<ManualPlacements>()
+            .borrow_mut()
+            .clients
+            .insert(id);
+        state.client_set.move_client_to_tag(&id, &tag);
+    } else {
+        return Ok(false);
+    }
+    x.refresh(state)?;
+
+    Ok(false)
+}
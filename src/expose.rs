@@ -0,0 +1,160 @@
+//! An expose-style overview of every window. Without a compositor there's no
+//! scaling windows down, so instead they're all gathered onto one workspace in
+//! a grid to pick from.
+//!
+//! `M-w` opens the overview, and once there `M-j`/`M-k` move between windows
+//! as usual. `M-w` again picks the focused window and `M-S-w` backs out, and
+//! either way everything goes back to the tag it came from. Going to another
+//! tag while the overview is open backs out of it too.
+use crate::{
+    backfill_gaps, fullscreen::fullscreen_clients, get_appearance, grid, ignored_clients,
+    is_hidden_tag,
+};
+use penrose::{
+    core::{layout::LayoutStack, State},
+    x::{XConn, XConnExt},
+    Result, Xid,
+};
+use std::collections::VecDeque;
+use tracing::{debug, info};
+
+/// Where windows are gathered while the overview is open.
+pub const EXPOSE_TAG: &str = "expose";
+
+#[derive(Debug, Default)]
+pub struct Expose {
+    /// Every gathered client and the tag it came from, with each tag's focused
+    /// client last so that it's focused again once they're put back
+    origins: Vec<(Xid, String)>,
+    previous_tag: String,
+    previous_focus: Option<Xid>,
+}
+
+fn grid_layout() -> LayoutStack {
+    LayoutStack::new(
+        VecDeque::default(),
+        grid(&get_appearance()),
+        VecDeque::default(),
+    )
+}
+
+/// Whether the overview is open.
+pub fn is_exposing<X: XConn + 'static>(state: &mut State<X>) -> bool {
+    !state
+        .extension_or_default::<Expose>()
+        .borrow()
+        .origins
+        .is_empty()
+}
+
+/// Key handler opening the overview, or picking the focused window if it's
+/// already open.
+pub fn toggle_expose<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    if is_exposing(state) {
+        let selected = state.client_set.current_client().cloned();
+        return restore(state, x, selected);
+    }
+
+    // Floating windows would only cover up the grid
+    let mut ignored = ignored_clients(state);
+    ignored.extend(fullscreen_clients(state));
+    let mut origins = Vec::new();
+    for workspace in state.client_set.workspaces() {
+        if is_hidden_tag(workspace.tag()) {
+            continue;
+        }
+        let focus = workspace.focus();
+        let tag = workspace.tag().to_string();
+        let clients = workspace
+            .clients()
+            .filter(|client| Some(*client) != focus)
+            .chain(focus)
+            .filter(|client| !ignored.contains(*client));
+        origins.extend(clients.map(|client| (*client, tag.clone())));
+    }
+    if origins.is_empty() {
+        debug!("No windows to show");
+        return Ok(());
+    }
+
+    if state.client_set.workspace(EXPOSE_TAG).is_none() {
+        state.client_set.add_workspace(EXPOSE_TAG, grid_layout())?;
+    }
+    let previous_focus = state.client_set.current_client().cloned();
+    let previous_tag = state.client_set.current_tag().to_string();
+    for (client, _) in &origins {
+        state.client_set.move_client_to_tag(client, EXPOSE_TAG);
+    }
+    state.client_set.focus_tag(EXPOSE_TAG);
+    if let Some(focus) = previous_focus {
+        state.client_set.focus_client(&focus);
+    }
+    info!(count = origins.len(), "Showing all windows");
+    *state.extension_or_default::<Expose>().borrow_mut() = Expose {
+        origins,
+        previous_tag,
+        previous_focus,
+    };
+
+    x.refresh(state)
+}
+
+/// Key handler backing out of the overview, leaving focus where it was before.
+pub fn cancel_expose<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    restore(state, x, None)
+}
+
+/// Put every window back on its own tag and focus `selected`, or whatever was
+/// focused before the overview opened if nothing was picked.
+fn restore<X: XConn + 'static>(state: &mut State<X>, x: &X, selected: Option<Xid>) -> Result<()> {
+    let expose = std::mem::take(&mut *state.extension_or_default::<Expose>().borrow_mut());
+    if expose.origins.is_empty() {
+        return Ok(());
+    }
+    let exposing = state.client_set.current_tag() == EXPOSE_TAG;
+
+    for (client, tag) in &expose.origins {
+        if state.client_set.contains(client) {
+            state.client_set.move_client_to_tag(client, tag);
+        }
+    }
+    // Anything that turned up in the meantime goes where we came from
+    let stragglers = state
+        .client_set
+        .workspace(EXPOSE_TAG)
+        .map(|workspace| workspace.clients().cloned().collect::<Vec<_>>())
+        .unwrap_or_default();
+    for client in stragglers {
+        state
+            .client_set
+            .move_client_to_tag(&client, &expose.previous_tag);
+    }
+
+    // If we left the overview for another tag, that's where we stay
+    if exposing {
+        let target = selected
+            .or(expose.previous_focus)
+            .filter(|client| state.client_set.contains(client));
+        let tag = target
+            .and_then(|client| state.client_set.tag_for_client(&client))
+            .map(String::from)
+            .unwrap_or(expose.previous_tag);
+        state.client_set.focus_tag(&tag);
+        if let Some(client) = target {
+            state.client_set.focus_client(&client);
+        }
+    }
+    info!(?selected, "Put windows back after showing them all");
+
+    backfill_gaps(state, x)?;
+    x.refresh(state)
+}
+
+/// Refresh hook backing out of the overview if we've gone to another tag.
+pub fn restore_on_leave<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    if is_exposing(state) && state.client_set.current_tag() != EXPOSE_TAG {
+        return restore(state, x, None);
+    }
+
+    Ok(())
+}
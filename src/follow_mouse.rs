@@ -0,0 +1,47 @@
+//! Focus following the mouse, switched on and off at runtime with `M-S-m`.
+//!
+//! penrose's own `focus_follow_mouse` is fixed once the WM starts, so it stays
+//! off and this event hook does the same job while it's turned on.
+use penrose::{
+    core::State,
+    x::{event::XEvent, XConn, XConnExt},
+    Result,
+};
+use tracing::info;
+
+#[derive(Debug, Default)]
+pub struct FollowMouse {
+    enabled: bool,
+}
+
+/// Key handler turning focus follows mouse on or off.
+pub fn toggle_follow_mouse<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    let follow = state.extension_or_default::<FollowMouse>();
+    let mut follow = follow.borrow_mut();
+    follow.enabled = !follow.enabled;
+    info!(enabled = follow.enabled, "Toggled focus follows mouse");
+
+    Ok(())
+}
+
+/// Event hook focusing whichever client the pointer moves into, while focus
+/// follows mouse is on.
+pub fn follow_mouse_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    let client = match event {
+        XEvent::Enter(pointer) => pointer.id,
+        _ => return Ok(true),
+    };
+    if !state.extension_or_default::<FollowMouse>().borrow().enabled {
+        return Ok(true);
+    }
+    if state.client_set.contains(&client) && state.client_set.current_client() != Some(&client) {
+        state.client_set.focus_client(&client);
+        x.refresh(state)?;
+    }
+
+    Ok(true)
+}
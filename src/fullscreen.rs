@@ -0,0 +1,247 @@
+//! Honouring `_NET_WM_STATE_FULLSCREEN` requests from clients.
+//!
+//! Fullscreen clients are floated over the whole of their screen so the layout
+//! leaves them alone, and go back to how they were, tiled or floating, when
+//! they ask to leave fullscreen again.
+use crate::ForgetClient;
+use penrose::{
+    core::State,
+    pure::geometry::Rect,
+    x::{
+        atom::Atom,
+        event::{ClientMessageData, XEvent},
+        property::Prop,
+        XConn, XConnExt,
+    },
+    Result, Xid,
+};
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+const FULLSCREEN: &str = "_NET_WM_STATE_FULLSCREEN";
+
+/// `_NET_WM_STATE` actions, from the EWMH spec.
+const STATE_REMOVE: u32 = 0;
+const STATE_ADD: u32 = 1;
+const STATE_TOGGLE: u32 = 2;
+
+#[derive(Debug, Default)]
+pub struct FullscreenClients {
+    pub clients: HashSet<Xid>,
+    /// Where fullscreen clients that were floating beforehand go back to
+    floating: HashMap<Xid, Rect>,
+}
+
+impl ForgetClient for FullscreenClients {
+    fn forget_client(&mut self, client: Xid) {
+        self.clients.remove(&client);
+        self.floating.remove(&client);
+    }
+}
+
+/// Clients currently fullscreen.
+pub fn fullscreen_clients<X: XConn + 'static>(state: &mut State<X>) -> HashSet<Xid> {
+    let fullscreen = state.extension_or_default::<FullscreenClients>();
+    let mut fullscreen = fullscreen.borrow_mut();
+    fullscreen
+        .clients
+        .retain(|client| state.client_set.contains(client));
+    fullscreen.clients.clone()
+}
+
+/// A request from a managed client to change one of its `_NET_WM_STATE`
+/// properties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WmStateRequest {
+    pub client: Xid,
+    pub action: u32,
+    /// Whether the same message changes another property as well, which the
+    /// hooks after whoever handles this one still need to see
+    pub changes_others: bool,
+}
+
+/// Whether the properties named in a `_NET_WM_STATE` message's `data` include
+/// any besides `atom`.
+fn changes_others(data: &[u32; 5], atom: u32) -> bool {
+    [data[1], data[2]].iter().any(|a| *a != 0 && *a != atom)
+}
+
+/// A request from a managed client to change its `_NET_WM_STATE` `name`
+/// property.
+pub fn wm_state_request<X: XConn>(
+    event: &XEvent,
+    state: &State<X>,
+    x: &X,
+    name: &str,
+) -> Result<Option<WmStateRequest>> {
+    let (client, data) = match event {
+        XEvent::ClientMessage(message) if message.dtype == Atom::NetWmState.as_ref() => {
+            match message.data {
+                ClientMessageData::U32(data) => (message.id, data),
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    if !state.client_set.contains(&client) {
+        return Ok(None);
+    }
+    let atom = *x.intern_atom(name)?;
+    if data[1] != atom && data[2] != atom {
+        return Ok(None);
+    }
+
+    Ok(Some(WmStateRequest {
+        client,
+        action: data[0],
+        changes_others: changes_others(&data, atom),
+    }))
+}
+
+/// Whether a property should be set after applying `action` to it, given
+/// whether it is now. `None` for actions we don't know about.
+pub fn apply_state_action(action: u32, current: bool) -> Option<bool> {
+    match action {
+        STATE_REMOVE => Some(false),
+        STATE_ADD => Some(true),
+        STATE_TOGGLE => Some(!current),
+        _ => None,
+    }
+}
+
+/// `atoms` with `atom` added or removed, leaving everything else alone.
+pub fn with_state_atom(mut atoms: Vec<String>, atom: &str, present: bool) -> Vec<String> {
+    atoms.retain(|a| a != atom);
+    if present {
+        atoms.push(atom.to_string());
+    }
+    atoms
+}
+
+/// Add `atom` to the client's `_NET_WM_STATE`, or remove it, keeping whatever
+/// other states it has.
+pub fn set_state_atom<X: XConn>(client: Xid, atom: &str, present: bool, x: &X) -> Result<()> {
+    let atoms = match x.get_prop(client, Atom::NetWmState.as_ref())? {
+        Some(Prop::Atom(atoms)) => atoms,
+        _ => Vec::new(),
+    };
+    x.set_prop(
+        client,
+        Atom::NetWmState.as_ref(),
+        Prop::Atom(with_state_atom(atoms, atom, present)),
+    )
+}
+
+/// Event hook handling `_NET_WM_STATE` fullscreen requests. Messages that
+/// change something else too are passed on, e.g. for [crate::urgent].
+pub fn fullscreen_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    let WmStateRequest {
+        client,
+        action,
+        changes_others,
+    } = match wm_state_request(event, state, x, FULLSCREEN)? {
+        Some(request) => request,
+        None => return Ok(true),
+    };
+
+    let is_fullscreen = state
+        .extension_or_default::<FullscreenClients>()
+        .borrow()
+        .clients
+        .contains(&client);
+    let fullscreen = match apply_state_action(action, is_fullscreen) {
+        Some(fullscreen) => fullscreen,
+        None => return Ok(true),
+    };
+    if fullscreen != is_fullscreen {
+        set_fullscreen(client, fullscreen, state, x)?;
+    }
+
+    Ok(changes_others)
+}
+
+fn set_fullscreen<X: XConn + 'static>(
+    client: Xid,
+    fullscreen: bool,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    debug!(?client, fullscreen, "Changing fullscreen state");
+    let extension = state.extension_or_default::<FullscreenClients>();
+    if fullscreen {
+        // Fill the screen showing the client, if it's on one
+        let screen = state
+            .client_set
+            .screens()
+            .find(|screen| screen.workspace.clients().any(|c| *c == client))
+            .unwrap_or_else(|| state.client_set.current_screen())
+            .geometry();
+        let mut extension = extension.borrow_mut();
+        if state.client_set.is_floating(&client) {
+            extension
+                .floating
+                .insert(client, x.client_geometry(client)?);
+        }
+        state.client_set.float(client, screen)?;
+        set_state_atom(client, FULLSCREEN, true, x)?;
+        extension.clients.insert(client);
+    } else {
+        let mut extension = extension.borrow_mut();
+        match extension.floating.remove(&client) {
+            Some(r) => state.client_set.float(client, r)?,
+            None => {
+                state.client_set.sink(&client);
+            }
+        }
+        set_state_atom(client, FULLSCREEN, false, x)?;
+        extension.clients.remove(&client);
+    }
+
+    x.refresh(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fullscreen_leaves_other_window_states_alone() {
+        let states = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let attention = "_NET_WM_STATE_DEMANDS_ATTENTION";
+
+        assert_eq!(
+            with_state_atom(states(&[attention]), FULLSCREEN, true),
+            states(&[attention, FULLSCREEN])
+        );
+        assert_eq!(
+            with_state_atom(states(&[attention, FULLSCREEN]), FULLSCREEN, true),
+            states(&[attention, FULLSCREEN])
+        );
+        assert_eq!(
+            with_state_atom(states(&[FULLSCREEN, attention]), FULLSCREEN, false),
+            states(&[attention])
+        );
+    }
+
+    #[test]
+    fn requests_changing_two_states_are_passed_on() {
+        let (fullscreen, attention) = (400, 401);
+
+        assert!(!changes_others(
+            &[STATE_ADD, fullscreen, 0, 1, 0],
+            fullscreen
+        ));
+        assert!(!changes_others(
+            &[STATE_ADD, 0, fullscreen, 1, 0],
+            fullscreen
+        ));
+        assert!(changes_others(
+            &[STATE_ADD, fullscreen, attention, 1, 0],
+            fullscreen
+        ));
+    }
+}
@@ -0,0 +1,93 @@
+//! Locking the screen after the user has been idle for a while.
+//!
+//! A background thread polls the XScreenSaver extension for how long it's been
+//! since the last input, and wakes up [idle_lock_listener] once that passes
+//! the configured `idle_minutes`. Locking is skipped while a fullscreen client
+//! is showing, so videos don't get locked mid-watch.
+use crate::{fullscreen::fullscreen_clients, get_lock_config, wake::Waker};
+use penrose::{
+    core::State,
+    util,
+    x::{event::XEvent, XConn},
+    Result,
+};
+use std::{thread, time::Duration};
+use tracing::{debug, info, warn};
+use x11rb::{connection::Connection, protocol::screensaver::ConnectionExt};
+
+const WAKE_ATOM: &str = "_WENDY_IDLE";
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Startup hook starting the idle timer, if there's a timeout and a lock
+/// command to run.
+pub fn start_idle_lock<X: XConn + 'static>(_: &mut State<X>, _: &X) -> Result<()> {
+    let lock = get_lock_config();
+    let timeout = match (lock.idle_minutes, lock.command) {
+        (Some(minutes), Some(_)) => Duration::from_secs(minutes * 60),
+        _ => {
+            debug!("No idle timeout configured, not locking automatically");
+            return Ok(());
+        }
+    };
+    let waker = Waker::new(WAKE_ATOM)?;
+    let (conn, screen) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+    info!("Locking after {timeout:?} idle");
+
+    thread::spawn(move || {
+        // Only lock once per stretch of idleness
+        let mut locked = false;
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let idle = match conn
+                .screensaver_query_info(root)
+                .map_err(|e| e.to_string())
+                .and_then(|cookie| cookie.reply().map_err(|e| e.to_string()))
+            {
+                Ok(info) => Duration::from_millis(info.ms_since_user_input.into()),
+                Err(e) => {
+                    warn!("Unable to query idle time, giving up on locking when idle: {e}");
+                    return;
+                }
+            };
+            if idle < timeout {
+                locked = false;
+            } else if !locked {
+                locked = true;
+                if let Err(e) = waker.wake() {
+                    warn!("Unable to wake up to lock the screen: {e}");
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// Event hook locking the screen once the idle timer goes off.
+pub fn idle_lock_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    _: &X,
+) -> Result<bool> {
+    match event {
+        XEvent::ClientMessage(message) if message.dtype == WAKE_ATOM => (),
+        _ => return Ok(true),
+    }
+
+    let fullscreen = fullscreen_clients(state);
+    let watching = state
+        .client_set
+        .screens()
+        .any(|screen| screen.workspace.clients().any(|c| fullscreen.contains(c)));
+    if watching {
+        info!("Idle, but not locking over a fullscreen window");
+        return Ok(false);
+    }
+    if let Some(command) = get_lock_config().command {
+        info!("Idle, locking the screen");
+        util::spawn(command)?;
+    }
+
+    Ok(false)
+}
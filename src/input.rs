@@ -0,0 +1,122 @@
+//! ICCCM input models. Clients that set `WM_TAKE_FOCUS` in `WM_PROTOCOLS`
+//! expect to be sent a `WM_TAKE_FOCUS` message when they're focused, and the
+//! "globally active" ones (mostly Java and Electron apps) don't set the input
+//! hint at all, so focusing them the usual way can leave keyboard input going
+//! nowhere.
+//!
+//! penrose still focuses them as normal, we just send the message on top once
+//! it has.
+use crate::ForgetClient;
+use penrose::{
+    core::State,
+    x::{atom::Atom, event::ClientMessageKind, property::Prop, XConn},
+    Result, Xid,
+};
+use std::collections::HashMap;
+use tracing::debug;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputModel {
+    NoInput,
+    Passive,
+    LocallyActive,
+    GloballyActive,
+}
+
+impl InputModel {
+    fn new(input_hint: bool, take_focus: bool) -> Self {
+        match (input_hint, take_focus) {
+            (false, false) => InputModel::NoInput,
+            (true, false) => InputModel::Passive,
+            (true, true) => InputModel::LocallyActive,
+            (false, true) => InputModel::GloballyActive,
+        }
+    }
+
+    fn wants_take_focus(&self) -> bool {
+        matches!(self, InputModel::LocallyActive | InputModel::GloballyActive)
+    }
+}
+
+/// Input models for the clients that want `WM_TAKE_FOCUS`, and who we last
+/// sent it to.
+#[derive(Debug, Default)]
+pub struct InputModels {
+    models: HashMap<Xid, InputModel>,
+    last_focused: Option<Xid>,
+}
+
+impl ForgetClient for InputModels {
+    fn forget_client(&mut self, client: Xid) {
+        self.models.remove(&client);
+        if self.last_focused == Some(client) {
+            self.last_focused = None;
+        }
+    }
+}
+
+fn input_model<X: XConn>(client: Xid, x: &X) -> InputModel {
+    // Clients that don't set WM_HINTS get the input hint assumed
+    let input_hint = match x.get_prop(client, Atom::WmHints.as_ref()).ok().flatten() {
+        Some(Prop::WmHints(hints)) => hints.accepts_input,
+        _ => true,
+    };
+    let take_focus = match x
+        .get_prop(client, Atom::WmProtocols.as_ref())
+        .ok()
+        .flatten()
+    {
+        Some(Prop::Atom(protocols)) => protocols
+            .iter()
+            .any(|protocol| protocol == Atom::WmTakeFocus.as_ref()),
+        _ => false,
+    };
+
+    InputModel::new(input_hint, take_focus)
+}
+
+/// Manage hook recording the input model of clients that need special
+/// treatment.
+pub fn record_input_model<X: XConn + 'static>(
+    client: Xid,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    let model = input_model(client, x);
+    if !model.wants_take_focus() {
+        return Ok(());
+    }
+    let models = state.extension_or_default::<InputModels>();
+    let mut models = models.borrow_mut();
+    models.models.insert(client, model);
+    models
+        .models
+        .retain(|client, _| state.client_set.contains(client));
+    debug!(clients = ?models.models, "Clients taking focus themselves");
+
+    Ok(())
+}
+
+/// Refresh hook sending `WM_TAKE_FOCUS` to clients that want it whenever they
+/// gain focus.
+pub fn send_take_focus<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let focus = state.client_set.current_client().cloned();
+    let models = state.extension_or_default::<InputModels>();
+    let mut models = models.borrow_mut();
+    if models.last_focused == focus {
+        return Ok(());
+    }
+    models.last_focused = focus;
+
+    let client = match focus {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    if let Some(model) = models.models.get(&client) {
+        debug!(?client, ?model, "Sending WM_TAKE_FOCUS");
+        let message = ClientMessageKind::TakeFocus(client).as_message(x)?;
+        x.send_client_message(message)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+//! Marking windows we create ourselves, like the alt-tab overlay, so they're
+//! never mistaken for clients if they ever end up managed: they stay out of
+//! the MRU lists, placement and `backfill_gaps`.
+use penrose::{x::XConn, Result, Xid};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{AtomEnum, ConnectionExt, PropMode},
+    wrapper::ConnectionExt as _,
+};
+
+const WM_INTERNAL: &str = "_WENDY_INTERNAL";
+
+/// Whether `client` is one of our own windows.
+pub fn is_wm_internal<X: XConn>(client: Xid, x: &X) -> bool {
+    x.get_prop(client, WM_INTERNAL).ok().flatten().is_some()
+}
+
+/// Mark `window` as one of our own. This needs doing whenever we create one.
+pub fn mark_wm_internal<C: Connection>(conn: &C, window: u32) -> Result<()> {
+    let atom = conn
+        .intern_atom(false, WM_INTERNAL.as_bytes())?
+        .reply()?
+        .atom;
+    conn.change_property32(PropMode::REPLACE, window, atom, AtomEnum::CARDINAL, &[1])?;
+
+    Ok(())
+}
@@ -0,0 +1,316 @@
+//! A control socket for scripting the WM, bound at `$XDG_RUNTIME_DIR/wendy.sock`
+//! (or a per-user, per-display socket in the temp dir if that isn't set).
+//!
+//! The wire format is line based: each line sent is a single command, and each
+//! command gets a single line back, either `OK` or `ERR <msg>`. Commands that
+//! return something put it after the `OK`.
+//!
+//! ```text
+//! focus-tag <tag>
+//! task-switch <global|workspace|same-app> <forward|backward>
+//! cycle-workspace <tag> [forward|backward]
+//! rename <tag> [name]
+//! status
+//! subscribe
+//! ```
+//!
+//! `task-switch` runs the same switching as alt-tab, and replies with the id of
+//! the window it left focused (or `none`).
+//!
+//! `rename` gives a tag a name to show in bars, and leaving out the name goes
+//! back to showing the tag.
+//!
+//! `status` replies with a single line of JSON, see [crate::status::Status].
+//! `subscribe` replies `OK` and then keeps the connection open, sending the
+//! status as a new line of JSON whenever the focused tag, the focused client,
+//! the windows on a tag, which of them want attention or a tag's name change.
+//!
+//! Connections are serviced on background threads, but every command runs on
+//! the WM's own thread: the socket thread queues the command up and then wakes
+//! up [ipc_listener] to run it.
+use crate::{
+    cycle_workspace,
+    status::{self, Fingerprint},
+    tag_names::{self, TagNames},
+    task_switch,
+    urgent::urgent_clients,
+    wake::Waker,
+    Direction, SwitchContext,
+};
+use penrose::{
+    core::State,
+    x::{event::XEvent, XConn, XConnExt},
+    Error, Result,
+};
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    os::unix::{
+        fs::MetadataExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+use tracing::{info, warn};
+
+const WAKE_ATOM: &str = "_WENDY_IPC";
+
+/// Where the control socket lives. The temp dir is shared, so a socket there
+/// is named after our uid and display to keep other sessions off it.
+pub fn socket_path() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR") {
+        return Ok(PathBuf::from(dir).join("wendy.sock"));
+    }
+    let uid = fs::metadata("/proc/self")?.uid();
+    let display = env::var("DISPLAY").unwrap_or_default();
+    Ok(env::temp_dir().join(format!("wendy-{uid}{display}.sock")))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    FocusTag(String),
+    TaskSwitch(SwitchContext, Direction),
+    CycleWorkspace(String, Direction),
+    Rename(String, String),
+    Status,
+    Subscribe,
+}
+
+fn parse_direction(direction: &str) -> std::result::Result<Direction, String> {
+    match direction {
+        "forward" => Ok(Direction::Forward),
+        "backward" => Ok(Direction::Backward),
+        _ => Err(format!("unknown direction: {direction}")),
+    }
+}
+
+fn parse_command(line: &str) -> std::result::Result<Command, String> {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+        ["focus-tag", tag] => Ok(Command::FocusTag(tag.to_string())),
+        ["task-switch", context, direction] => {
+            let context = match *context {
+                "global" => SwitchContext::Global,
+                "workspace" => SwitchContext::Workspace,
+                "same-app" => SwitchContext::SameApp,
+                _ => return Err(format!("unknown context: {context}")),
+            };
+            Ok(Command::TaskSwitch(context, parse_direction(direction)?))
+        }
+        ["cycle-workspace", tag] => {
+            Ok(Command::CycleWorkspace(tag.to_string(), Direction::Forward))
+        }
+        ["cycle-workspace", tag, direction] => Ok(Command::CycleWorkspace(
+            tag.to_string(),
+            parse_direction(direction)?,
+        )),
+        ["rename", tag, name @ ..] => Ok(Command::Rename(tag.to_string(), name.join(" "))),
+        ["status"] => Ok(Command::Status),
+        ["subscribe"] => Ok(Command::Subscribe),
+        [] => Err("empty command".to_string()),
+        [command, ..] => Err(format!("unknown command: {command}")),
+    }
+}
+
+#[derive(Debug)]
+struct Request {
+    line: String,
+    reply: Sender<String>,
+}
+
+/// Connections waiting on status updates.
+#[derive(Debug, Default)]
+struct Subscribers {
+    senders: Vec<Sender<String>>,
+}
+
+/// Commands waiting for the WM thread to pick them up.
+#[derive(Debug)]
+pub struct IpcRequests {
+    requests: Receiver<Request>,
+}
+
+/// Startup hook binding the control socket.
+pub fn start_ipc<X: XConn + 'static>(state: &mut State<X>, _x: &X) -> Result<()> {
+    let path = socket_path()?;
+    // Clean up after a previous run that didn't get to, unless something is
+    // still answering on the socket
+    if path.exists() {
+        if UnixStream::connect(&path).is_ok() {
+            warn!("Not listening for commands: {} is in use", path.display());
+            return Ok(());
+        }
+        fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    let waker = Waker::new(WAKE_ATOM)?;
+
+    let (sender, requests) = mpsc::channel();
+    state.add_extension(IpcRequests { requests });
+    info!("Listening for commands on {}", path.display());
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let sender = sender.clone();
+                    let waker = waker.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = serve(stream, sender, &waker) {
+                            warn!("IPC connection failed: {e}");
+                        }
+                    });
+                }
+                Err(e) => warn!("Failed to accept IPC connection: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn serve(
+    stream: UnixStream,
+    sender: Sender<Request>,
+    waker: &Waker,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let (reply, response) = mpsc::channel();
+        sender.send(Request { line: line?, reply })?;
+        waker.wake()?;
+        // Subscribers keep getting lines until one of us hangs up
+        for line in response.iter() {
+            writeln!(writer, "{line}")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Event hook running any commands that the socket thread has queued up.
+pub fn ipc_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    match event {
+        XEvent::ClientMessage(message) if message.dtype == WAKE_ATOM => (),
+        _ => return Ok(true),
+    }
+    let requests = match state.extension::<IpcRequests>() {
+        Ok(requests) => requests,
+        Err(_) => return Ok(false),
+    };
+
+    let pending = requests.borrow().requests.try_iter().collect::<Vec<_>>();
+    for request in pending {
+        let response = match parse_command(&request.line) {
+            Ok(Command::Subscribe) => {
+                // Start the newcomer off with where things are at
+                let status = match status_json(state, x) {
+                    Ok(status) => status,
+                    Err(e) => {
+                        let _ = request.reply.send(format!("ERR {e}"));
+                        continue;
+                    }
+                };
+                if request.reply.send("OK".to_string()).is_ok()
+                    && request.reply.send(status).is_ok()
+                {
+                    state
+                        .extension_or_default::<Subscribers>()
+                        .borrow_mut()
+                        .senders
+                        .push(request.reply);
+                }
+                continue;
+            }
+            Ok(command) => match run_command(command, state, x) {
+                Ok(None) => "OK".to_string(),
+                Ok(Some(output)) => format!("OK {output}"),
+                Err(e) => format!("ERR {e}"),
+            },
+            Err(e) => format!("ERR {e}"),
+        };
+        // The client hanging up on us isn't our problem
+        let _ = request.reply.send(response);
+    }
+
+    Ok(false)
+}
+
+/// Run `command`, returning anything it has to say for itself.
+fn run_command<X: XConn + 'static>(
+    command: Command,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<Option<String>> {
+    match command {
+        Command::FocusTag(tag) => {
+            if state.client_set.workspace(&tag).is_none() {
+                return Err(Error::Custom(format!("unknown tag: {tag}")));
+            }
+            state.client_set.focus_tag(&tag);
+        }
+        Command::TaskSwitch(context, direction) => {
+            // This refreshes for itself
+            task_switch(state, x, context, direction)?;
+            let focused = state
+                .client_set
+                .current_client()
+                .map_or_else(|| "none".to_string(), |client| (**client).to_string());
+            return Ok(Some(focused));
+        }
+        Command::CycleWorkspace(tag, direction) => {
+            cycle_workspace(state, &tag, direction)?;
+        }
+        Command::Rename(tag, name) => {
+            if state.client_set.workspace(&tag).is_none() {
+                return Err(Error::Custom(format!("unknown tag: {tag}")));
+            }
+            tag_names::rename(state, &tag, &name);
+        }
+        Command::Status => return status_json(state, x).map(Some),
+        // Handled in ipc_listener, since it hangs on to the connection
+        Command::Subscribe => return Ok(None),
+    }
+
+    x.refresh(state)?;
+    Ok(None)
+}
+
+fn status_json<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<String> {
+    let status = status::status(state, x);
+    serde_json::to_string(&status)
+        .map_err(|e| Error::Custom(format!("unable to serialize status: {e}")))
+}
+
+/// Refresh hook pushing the status out to subscribers when it changes.
+pub fn notify_subscribers<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let subscribers = state.extension_or_default::<Subscribers>();
+    if subscribers.borrow().senders.is_empty() {
+        return Ok(());
+    }
+    let urgent = urgent_clients(state);
+    let names = state.extension_or_default::<TagNames>();
+    let fingerprint = state.extension_or_default::<Fingerprint>();
+    let changed =
+        fingerprint
+            .borrow_mut()
+            .update(&state.client_set, &urgent, &names.borrow().names);
+    if !changed {
+        return Ok(());
+    }
+
+    let line = status_json(state, x)?;
+    // Anyone who's gone away gets dropped here
+    subscribers
+        .borrow_mut()
+        .senders
+        .retain(|sender| sender.send(line.clone()).is_ok());
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! A dmenu launcher that offers the pinned apps first. Picking one goes to its
+//! tag the same as its `M-{tag}` binding would, launching it if need be, and
+//! anything else is run like `dmenu_run` would.
+//!
+//! dmenu runs on a background thread so the WM carries on while it's open, and
+//! the selection is handed back to [launcher_listener] to act on.
+use crate::{focus_or_launch_tag, get_pinned_apps, wake::Waker};
+use penrose::{
+    core::State,
+    util,
+    x::{event::XEvent, XConn},
+    Result,
+};
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+use tracing::{debug, warn};
+
+const WAKE_ATOM: &str = "_WENDY_LAUNCH";
+
+#[derive(Debug)]
+pub struct Launcher {
+    sender: Sender<String>,
+    selections: Receiver<String>,
+    waker: Waker,
+}
+
+/// Startup hook getting ready to hear back from dmenu.
+pub fn start_launcher<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    let (sender, selections) = mpsc::channel();
+    state.add_extension(Launcher {
+        sender,
+        selections,
+        waker: Waker::new(WAKE_ATOM)?,
+    });
+
+    Ok(())
+}
+
+/// Everything else on the `$PATH`, as `dmenu_run` would list it.
+fn path_executables() -> Vec<String> {
+    match Command::new("dmenu_path").stderr(Stdio::null()).output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect(),
+        Err(e) => {
+            warn!("Unable to run dmenu_path, only offering pinned apps: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// Ask dmenu to pick one of `choices`. `None` if it was dismissed.
+fn choose(choices: &[String]) -> std::io::Result<Option<String>> {
+    let mut dmenu = Command::new("dmenu")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = dmenu.stdin.take() {
+        // dmenu going away early just means nothing was picked
+        let _ = stdin.write_all(choices.join("\n").as_bytes());
+    }
+    let output = dmenu.wait_with_output()?;
+    let selection = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    Ok(Some(selection).filter(|selection| !selection.is_empty()))
+}
+
+/// Key handler opening the launcher.
+pub fn launch<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    let (sender, waker) = {
+        let launcher = state.extension::<Launcher>()?;
+        let launcher = launcher.borrow();
+        (launcher.sender.clone(), launcher.waker.clone())
+    };
+    let mut pinned = get_pinned_apps(state)
+        .iter()
+        .map(|(tag, app)| (tag.clone(), app.command.clone()))
+        .collect::<Vec<_>>();
+    pinned.sort();
+
+    thread::spawn(move || {
+        let mut choices = pinned
+            .into_iter()
+            .map(|(_, command)| command)
+            .collect::<Vec<_>>();
+        let others = path_executables()
+            .into_iter()
+            .filter(|executable| !choices.contains(executable))
+            .collect::<Vec<_>>();
+        choices.extend(others);
+        match choose(&choices) {
+            Ok(Some(selection)) => {
+                if sender.send(selection).is_ok() {
+                    if let Err(e) = waker.wake() {
+                        warn!("Unable to hand the launcher selection back: {e}");
+                    }
+                }
+            }
+            Ok(None) => debug!("Launcher dismissed"),
+            Err(e) => warn!("Unable to run dmenu: {e}"),
+        }
+    });
+
+    Ok(())
+}
+
+/// Event hook acting on whatever was picked in the launcher.
+pub fn launcher_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    match event {
+        XEvent::ClientMessage(message) if message.dtype == WAKE_ATOM => (),
+        _ => return Ok(true),
+    }
+    let launcher = match state.extension::<Launcher>() {
+        Ok(launcher) => launcher,
+        Err(_) => return Ok(false),
+    };
+
+    let selections = launcher.borrow().selections.try_iter().collect::<Vec<_>>();
+    for selection in selections {
+        let tag = get_pinned_apps(state)
+            .iter()
+            .find(|(_, app)| app.command == selection)
+            .map(|(tag, _)| tag.clone());
+        match tag {
+            Some(tag) => focus_or_launch_tag(state, x, &tag)?,
+            None => util::spawn(selection)?,
+        }
+    }
+
+    Ok(false)
+}
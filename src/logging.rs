@@ -0,0 +1,65 @@
+//! How much gets logged.
+//!
+//! The filter comes from `$WENDY_LOG` if it's set, then `$RUST_LOG`, and is
+//! `info` otherwise. Both take the usual `EnvFilter` directives, e.g.
+//! `WENDY_LOG=wendy=debug`. `M-S-d` flips between that and `debug` while
+//! running, for when something needs looking into without a restart.
+use penrose::{core::State, x::XConn, Result};
+use std::{env, sync::OnceLock};
+use tracing::{info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+#[derive(Debug)]
+struct LogFilter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// The filter we started out with
+    base: String,
+}
+
+static LOG_FILTER: OnceLock<LogFilter> = OnceLock::new();
+
+/// Whether `M-S-d` has turned on debug logging.
+#[derive(Debug, Default)]
+pub struct DebugLogging {
+    enabled: bool,
+}
+
+fn base_filter() -> String {
+    env::var("WENDY_LOG")
+        .or_else(|_| env::var("RUST_LOG"))
+        .unwrap_or_else(|_| "info".to_string())
+}
+
+/// The filter for the main log output, which [toggle_debug_logging] can swap
+/// out later.
+pub fn reloadable_filter() -> reload::Layer<EnvFilter, Registry> {
+    let base = base_filter();
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(&base));
+    // Only the first subscriber set up gets to be reloaded
+    let _ = LOG_FILTER.set(LogFilter { handle, base });
+    filter
+}
+
+/// Key handler switching between debug logging and the usual filter.
+pub fn toggle_debug_logging<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    let filter = match LOG_FILTER.get() {
+        Some(filter) => filter,
+        None => return Ok(()),
+    };
+    let debug_logging = state.extension_or_default::<DebugLogging>();
+    let mut debug_logging = debug_logging.borrow_mut();
+    let spec = if debug_logging.enabled {
+        filter.base.as_str()
+    } else {
+        "debug"
+    };
+    match filter.handle.reload(EnvFilter::new(spec)) {
+        Ok(()) => {
+            debug_logging.enabled = !debug_logging.enabled;
+            info!("Now logging with {spec:?}");
+        }
+        Err(e) => warn!("Unable to change the log filter: {e}"),
+    }
+
+    Ok(())
+}
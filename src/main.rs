@@ -4,376 +4,2256 @@
 //! has multiple workspaces and simple client / workspace movement.
 use penrose::{
     builtin::{
-        actions::{exit, key_handler, modify_with, send_layout_message, spawn},
+        actions::{
+            exit,
+            floating::{MouseDragHandler, MouseResizeHandler},
+            key_handler, modify_with, send_layout_message,
+        },
         layout::{
             messages::{ExpandMain, IncMain, ShrinkMain},
-            Monocle,
+            transformers::Gaps,
+            Grid, MainAndStack, Monocle,
         },
     },
     core::{
         bindings::{
             keycodes_from_xmodmap, parse_keybindings_with_xmodmap, KeyCodeMask, KeyEventHandler,
-            ModifierKey,
+            ModifierKey, MouseButton, MouseEventHandler, MouseState,
         },
-        layout::LayoutStack,
-        Config, State, WindowManager,
+        layout::{Layout, LayoutStack},
+        ClientSet, Config, State, WindowManager,
     },
     extensions::hooks::add_ewmh_hooks,
     map,
-    pure::Screen,
+    pure::{geometry::Rect, Screen, Workspace},
     util,
     x::{
         atom::Atom,
         event::XEvent,
         property::Prop,
-        query::{AppName, ClassName, Query},
-        XConn, XConnExt,
+        query::{AppName, Query},
+        ClientAttr, ClientConfig, XConn, XConnExt,
     },
     x11rb::RustConn,
-    Result, Xid,
+    Color, Error, Result, Xid,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    env,
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{self, Stdio},
+    rc::Rc,
+    time::{Duration, Instant},
 };
-use std::collections::{HashMap, HashSet, VecDeque};
-use tracing_subscriber::{self, prelude::*};
+use tracing::{debug, debug_span, info, warn, Level};
+use tracing_subscriber::{self, filter::Targets, prelude::*};
 use x11rb::connection::RequestConnection;
 use x11rb::protocol::xkb::{self, ConnectionExt};
-use x11rb::protocol::xproto::ModMask;
+
+mod activate;
+mod config;
+mod desktops;
+mod expose;
+mod follow_mouse;
+mod fullscreen;
+mod idle;
+mod input;
+mod internal;
+mod ipc;
+mod launcher;
+mod logging;
+mod minimize;
+mod opacity;
+mod outputs;
+mod overflow;
+mod overlay;
+mod persist;
+mod queries;
+mod scratchpad;
+mod screens;
+mod stash;
+mod status;
+mod sticky;
+mod struts;
+mod swallow;
+mod tag_names;
+mod transients;
+mod urgent;
+mod wake;
+
+use config::{
+    Appearance, LayoutKind, LockConfig, PlacementConfig, RunningElsewhere, SwitchConfig,
+    SwitchModifier,
+};
+use fullscreen::fullscreen_clients;
+use overlay::AltTabOverlay;
+use persist::SavedPlacement;
+use queries::ClassNameRegex;
+use scratchpad::{is_scratchpad, scratchpad_client, SCRATCHPAD_TAG};
+use wake::Waker;
 
 #[derive(Debug)]
 struct PinnedApp<X: XConn> {
-    command: &'static str,
-    query: Box<dyn Query<X>>,
+    command: String,
+    queries: Vec<Box<dyn Query<X>>>,
+    /// For an app spanning several tags, which of its windows belong on this
+    /// one. Empty for apps with just the one tag.
+    tag_queries: Vec<Box<dyn Query<X>>>,
+    /// Extra environment variables to launch with
+    env: Vec<(String, String)>,
+    /// Where to launch from, rather than wherever we were started
+    cwd: Option<PathBuf>,
+    /// How many windows can share the tag before new ones overflow to another
+    max_clients: Option<usize>,
+    running_elsewhere: RunningElsewhere,
+    /// Used in place of the focused border colour for the app's windows
+    border: Option<Color>,
 }
 
-fn get_app_name<X: XConn>(client: Xid, x: &X) -> Option<String> {
+impl<X: XConn> PinnedApp<X> {
+    /// Whether any of our queries match `client`. A query that fails to run
+    /// (e.g. a missing property) is treated as not matching.
+    fn matches(&self, client: Xid, x: &X) -> bool {
+        let any_match = |queries: &[Box<dyn Query<X>>]| {
+            queries
+                .iter()
+                .any(|query| query.run(client, x).unwrap_or(false))
+        };
+        any_match(&self.queries) && (self.tag_queries.is_empty() || any_match(&self.tag_queries))
+    }
+
+    /// Launch the app. Like [util::spawn], but with our environment and
+    /// working directory applied.
+    fn spawn(&self) -> Result<()> {
+        let mut parts = self.command.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| Error::Custom("pinned app has an empty command".to_string()))?;
+        let mut command = process::Command::new(program);
+        command
+            .args(parts)
+            .envs(self.env.iter().map(|(key, value)| (key, value)))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        command.spawn()?;
+
+        Ok(())
+    }
+}
+
+fn get_app_classes<X: XConn>(client: Xid, x: &X) -> Vec<String> {
     match x.get_prop(client, Atom::WmClass.as_ref()).ok().flatten() {
-        Some(Prop::UTF8String(classes)) if !classes.is_empty() => Some(classes[0].clone()),
+        Some(Prop::UTF8String(classes)) => classes,
+        _ => Vec::new(),
+    }
+}
+
+fn get_app_name<X: XConn>(client: Xid, x: &X) -> Option<String> {
+    get_app_classes(client, x).into_iter().next()
+}
+
+fn get_string_prop<X: XConn>(client: Xid, prop: &str, x: &X) -> Option<String> {
+    match x.get_prop(client, prop).ok().flatten() {
+        Some(Prop::UTF8String(values)) => values.into_iter().next().filter(|v| !v.is_empty()),
         _ => None,
     }
 }
 
-fn get_pinned_apps<X: XConn>() -> HashMap<&'static str, PinnedApp<X>> {
-    HashMap::from([
+/// What to group `client` with other windows by. A client (or whatever
+/// launched it) can set `_WENDY_GROUP` to pick its own group, otherwise
+/// windows with a `WM_WINDOW_ROLE` are grouped by that within their app, and
+/// anything else is grouped by app.
+fn get_group_key<X: XConn>(client: Xid, x: &X) -> Option<String> {
+    if let Some(group) = get_string_prop(client, "_WENDY_GROUP", x) {
+        return Some(group);
+    }
+    let app_name = get_app_name(client, x)?;
+    match get_string_prop(client, "WM_WINDOW_ROLE", x) {
+        Some(role) => Some(format!("{app_name}:{role}")),
+        None => Some(app_name),
+    }
+}
+
+/// The client's title, preferring the UTF-8 `_NET_WM_NAME` over `WM_NAME`.
+fn get_title<X: XConn>(client: Xid, x: &X) -> Option<String> {
+    get_string_prop(client, Atom::NetWmName.as_ref(), x)
+        .or_else(|| get_string_prop(client, Atom::WmName.as_ref(), x))
+}
+
+fn get_window_title<X: XConn>(client: Xid, x: &X) -> String {
+    get_title(client, x)
+        .or_else(|| get_app_name(client, x))
+        .unwrap_or_else(|| format!("{client:?}"))
+}
+
+/// Whether `client` is a dialog or other helper window that shouldn't be tiled.
+fn is_floating_type<X: XConn>(client: Xid, x: &X) -> bool {
+    let transient = matches!(
+        x.get_prop(client, Atom::WmTransientFor.as_ref()).ok().flatten(),
+        Some(Prop::Window(parents)) if !parents.is_empty()
+    );
+    let helper_type = match x
+        .get_prop(client, Atom::NetWmWindowType.as_ref())
+        .ok()
+        .flatten()
+    {
+        Some(Prop::Atom(types)) => types
+            .iter()
+            .any(|t| t == "_NET_WM_WINDOW_TYPE_DIALOG" || t == "_NET_WM_WINDOW_TYPE_UTILITY"),
+        _ => false,
+    };
+
+    transient || helper_type
+}
+
+/// The float rules, kept like [PinnedApps] so that they aren't read in again
+/// for every new client.
+#[derive(Debug)]
+struct FloatRules<X: XConn> {
+    rules: Rc<Vec<config::FloatRule<X>>>,
+}
+
+fn get_float_rules<X: XConn + 'static>(state: &mut State<X>) -> Rc<Vec<config::FloatRule<X>>> {
+    if let Ok(float_rules) = state.extension::<FloatRules<X>>() {
+        return float_rules.borrow().rules.clone();
+    }
+    let path = config::config_path();
+    let rules = Rc::new(config::load_float_rules(&path).unwrap_or_else(|e| {
+        warn!("Failed to load float rules from {}: {e}", path.display());
+        Vec::new()
+    }));
+    state.add_extension(FloatRules {
+        rules: rules.clone(),
+    });
+    rules
+}
+
+/// Whether `client` should float: whatever the first matching `[[rule]]` says,
+/// or otherwise whether it's a dialog or helper window.
+fn wants_floating<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> bool {
+    let rules = get_float_rules(state);
+    let rule = rules.iter().find(|rule| {
+        rule.queries
+            .iter()
+            .any(|query| query.run(client, x).unwrap_or(false))
+    });
+    match rule {
+        Some(rule) => rule.float,
+        None => is_floating_type(client, x),
+    }
+}
+
+/// A `w` by `h` rect in the middle of `screen`.
+fn centered_in(w: u32, h: u32, screen: Rect) -> Rect {
+    let w = w.min(screen.w);
+    let h = h.min(screen.h);
+    Rect::new(
+        screen.x + (screen.w - w) / 2,
+        screen.y + (screen.h - h) / 2,
+        w,
+        h,
+    )
+}
+
+/// The pinned apps used when there is no config file to read them from.
+fn default_pinned_apps<X: XConn>() -> HashMap<String, PinnedApp<X>> {
+    let apps = vec![
         (
-            "1",
+            "1".to_string(),
             PinnedApp {
-                command: "emacs",
-                query: Box::new(AppName("emacs")),
+                command: "emacs".to_string(),
+                queries: vec![Box::new(AppName("emacs"))],
+                tag_queries: Vec::new(),
+                env: Vec::new(),
+                cwd: None,
+                max_clients: None,
+                running_elsewhere: RunningElsewhere::default(),
+                border: None,
             },
         ),
         (
-            "2",
+            "2".to_string(),
             PinnedApp {
-                command: "alacritty",
-                query: Box::new(AppName("Alacritty")),
+                command: "alacritty".to_string(),
+                queries: vec![Box::new(AppName("Alacritty"))],
+                tag_queries: Vec::new(),
+                env: Vec::new(),
+                cwd: None,
+                max_clients: None,
+                running_elsewhere: RunningElsewhere::default(),
+                border: None,
             },
         ),
         (
-            "3",
+            "3".to_string(),
             PinnedApp {
-                command: "chromium",
-                query: Box::new(ClassName("Chromium")),
+                command: "chromium".to_string(),
+                queries: vec![Box::new(
+                    ClassNameRegex::new("^[Cc]hromium").expect("chromium regex is valid"),
+                )],
+                tag_queries: Vec::new(),
+                env: Vec::new(),
+                cwd: None,
+                max_clients: None,
+                running_elsewhere: RunningElsewhere::default(),
+                border: None,
             },
         ),
         (
-            "4",
+            "4".to_string(),
             PinnedApp {
-                command: "DiscordCanary",
-                query: Box::new(AppName("DiscordCanary")),
+                command: "DiscordCanary".to_string(),
+                queries: vec![
+                    Box::new(AppName("DiscordCanary")),
+                    Box::new(AppName("discord")),
+                ],
+                tag_queries: Vec::new(),
+                env: Vec::new(),
+                cwd: None,
+                max_clients: None,
+                running_elsewhere: RunningElsewhere::default(),
+                border: None,
             },
         ),
         (
-            "5",
+            "5".to_string(),
             PinnedApp {
-                command: "slack",
-                query: Box::new(AppName("slack")),
+                command: "slack".to_string(),
+                queries: vec![Box::new(AppName("slack"))],
+                tag_queries: Vec::new(),
+                env: Vec::new(),
+                cwd: None,
+                max_clients: None,
+                running_elsewhere: RunningElsewhere::default(),
+                border: None,
             },
         ),
-    ])
+    ];
+    let conflicts = config::pinned_app_conflicts(
+        apps.iter()
+            .map(|(tag, app)| (vec![tag.as_str()], app.command.as_str())),
+    );
+    for conflict in conflicts {
+        warn!("Default pinned apps conflict: {conflict}");
+    }
+
+    apps.into_iter().collect()
+}
+
+/// The pinned apps, loaded on first use and kept until the config is reloaded
+/// so that their queries aren't rebuilt every time they're needed.
+#[derive(Debug)]
+struct PinnedApps<X: XConn> {
+    apps: Rc<HashMap<String, PinnedApp<X>>>,
+}
+
+fn load_pinned_apps<X: XConn>() -> HashMap<String, PinnedApp<X>> {
+    let path = config::config_path();
+    match config::load_pinned_apps(&path) {
+        Ok(apps) => apps,
+        Err(e) => {
+            warn!("Failed to load pinned apps from {}: {e}", path.display());
+            default_pinned_apps()
+        }
+    }
+}
+
+fn get_pinned_apps<X: XConn + 'static>(state: &mut State<X>) -> Rc<HashMap<String, PinnedApp<X>>> {
+    if let Ok(pinned) = state.extension::<PinnedApps<X>>() {
+        return pinned.borrow().apps.clone();
+    }
+    let apps = Rc::new(load_pinned_apps());
+    state.add_extension(PinnedApps { apps: apps.clone() });
+    apps
+}
+
+/// The queries for the main app, kept like [PinnedApps] so that they aren't
+/// read in again on every refresh.
+#[derive(Debug)]
+struct MainApp<X: XConn> {
+    queries: Rc<Vec<Box<dyn Query<X>>>>,
+}
+
+fn get_main_app<X: XConn + 'static>(state: &mut State<X>) -> Rc<Vec<Box<dyn Query<X>>>> {
+    if let Ok(main_app) = state.extension::<MainApp<X>>() {
+        return main_app.borrow().queries.clone();
+    }
+    let path = config::config_path();
+    let queries = Rc::new(config::load_main_app(&path).unwrap_or_else(|e| {
+        warn!("Failed to load the main app from {}: {e}", path.display());
+        Vec::new()
+    }));
+    state.add_extension(MainApp {
+        queries: queries.clone(),
+    });
+    queries
 }
 
 const TAGS: [&str; 10] = ["1", "2", "3", "4", "5", "6", "7", "8", "9", "10"];
 
-fn raw_key_bindings() -> HashMap<String, Box<dyn KeyEventHandler<RustConn>>> {
+/// The number key bound to `tag`. There's no 10 key, so tag "10" lives on 0.
+fn key_for_tag(tag: &str) -> &str {
+    if tag == "10" {
+        "0"
+    } else {
+        tag
+    }
+}
+
+/// Dragging windows around with Super held. These move windows in the floating
+/// layer, so grabbing a tiled window pulls it out of the layout first.
+fn mouse_bindings() -> HashMap<MouseState, Box<dyn MouseEventHandler<RustConn>>> {
+    map! {
+        map_keys: |(button, modifiers)| MouseState { button, modifiers };
+
+        (MouseButton::Left, vec![ModifierKey::Meta]) => MouseDragHandler::boxed_default(),
+        (MouseButton::Right, vec![ModifierKey::Meta]) => MouseResizeHandler::boxed_default(),
+    }
+}
+
+fn raw_key_bindings(
+    switching: &SwitchConfig,
+) -> HashMap<String, Box<dyn KeyEventHandler<RustConn>>> {
     let mut raw_bindings = map! {
         map_keys: |k: &str| k.to_string();
 
         "M-S-q" => modify_with(|cs| cs.kill_focused()),
-        "A-space" => spawn("dmenu_run"),
-        "M-Return" => spawn("alacritty"),
-        "M-A-Escape" => exit(),
-
-        "A-S-grave" => key_handler(move |_, _| Ok(())),
-        "A-grave" => key_handler(move |_, _| Ok(())),
-        "A-Tab" => key_handler(move |_, _| Ok(())),
-        "A-S-Tab" => key_handler(move |_, _| Ok(())),
-        "Alt_L" => key_handler(move |_, _| Ok(())),
-        "M-l" => spawn("xscreensaver-command --lock"),
+        "M-S-k" => key_handler(kill_app_windows),
+        "M-S-s" => key_handler(sticky::toggle_sticky),
+        "M-t" => key_handler(toggle_floating),
+        "M-u" => key_handler(urgent::focus_urgent),
+        "M-S-m" => key_handler(follow_mouse::toggle_follow_mouse),
+        "M-w" => key_handler(expose::toggle_expose),
+        "M-bracketleft" => key_handler(|state, x: &RustConn| {
+            shift_in_recent_clients(state, x, Direction::Backward)
+        }),
+        "M-bracketright" => key_handler(|state, x: &RustConn| {
+            shift_in_recent_clients(state, x, Direction::Forward)
+        }),
+        "M-S-w" => key_handler(expose::cancel_expose),
+        "M-S-r" => key_handler(reload_config),
+        "M-S-c" => key_handler(restart),
+        "M-S-d" => key_handler(logging::toggle_debug_logging),
+        "A-space" => key_handler(launcher::launch),
+        "M-Return" => key_handler(spawn_terminal),
+        // These do nothing under Monocle, but come in handy in MainAndStack
+        "M-j" => modify_with(|cs| cs.focus_down()),
+        "M-k" => modify_with(|cs| cs.focus_up()),
+        "M-S-Return" => modify_with(|cs| cs.swap_focus_and_head()),
+        // Resizing the main area would usually be M-h/M-l, but M-l locks the
+        // screen and M-comma/M-period move between screens.
+        "M-minus" => send_layout_message(|| ShrinkMain),
+        "M-S-equal" => key_handler(reset_layout),
+        "M-S-g" => key_handler(toggle_gaps),
+        "M-equal" => send_layout_message(|| ExpandMain),
+        "M-i" => send_layout_message(|| IncMain(1)),
+        "M-d" => send_layout_message(|| IncMain(-1)),
+        "M-space" => key_handler(|state, x: &RustConn| {
+            state.client_set.current_workspace_mut().next_layout();
+            x.refresh(state)
+        }),
+        "M-S-space" => key_handler(|state, x: &RustConn| {
+            state.client_set.current_workspace_mut().previous_layout();
+            x.refresh(state)
+        }),
+        "M-A-Escape" => key_handler(confirm_exit),
+        "M-Escape" => key_handler(focus_previous_tag),
+        "M-grave" => key_handler(scratchpad::toggle_scratchpad),
+        "M-S-grave" => key_handler(scratchpad::stash_focused),
+        "M-S-t" => key_handler(scratchpad::release_scratchpad),
+        "M-n" => key_handler(minimize::minimize_focused),
+        "M-S-n" => key_handler(minimize::restore_minimized),
+        "M-y" => key_handler(stash::stash_focused),
+        "M-S-y" => key_handler(stash::cycle_stash),
+        "M-o" => key_handler(focus_previous_client),
+        "M-Tab" => key_handler(|state, x: &RustConn| {
+            focus_screen_client(state, x, Direction::Forward)
+        }),
+        "M-S-Tab" => key_handler(|state, x: &RustConn| {
+            focus_screen_client(state, x, Direction::Backward)
+        }),
+        "M-p" => key_handler(transients::focus_transient_relative),
+        "M-g" => key_handler(gather_app_windows),
+        "M-period" => key_handler(|state, x: &RustConn| {
+            focus_adjacent_screen(state, x, Direction::Forward)
+        }),
+        "M-comma" => key_handler(|state, x: &RustConn| {
+            focus_adjacent_screen(state, x, Direction::Backward)
+        }),
+        "M-S-period" => key_handler(|state, x: &RustConn| {
+            move_focused_to_screen(state, x, Direction::Forward)
+        }),
+        "M-S-comma" => key_handler(|state, x: &RustConn| {
+            move_focused_to_screen(state, x, Direction::Backward)
+        }),
+
+        "M-l" => key_handler(|_, _| match get_lock_config().command {
+            Some(command) => util::spawn(command),
+            None => {
+                info!("No lock command is configured");
+                Ok(())
+            }
+        }),
     };
 
+    // Task switching is done by alt_tab_listener, these are just so that the
+    // key presses get grabbed.
+    for (key, context) in [
+        ("Tab", SwitchContext::Global),
+        ("grave", SwitchContext::Workspace),
+        ("backslash", SwitchContext::SameApp),
+    ] {
+        let modifier = switching.modifier_for(&context);
+        for key in [key.to_string(), format!("S-{key}")] {
+            let binding = format!("{}-{key}", modifier.binding_prefix());
+            if raw_bindings
+                .insert(binding.clone(), key_handler(move |_, _| Ok(())))
+                .is_some()
+            {
+                warn!("{binding} is used for task switching, overriding its usual binding");
+            }
+        }
+        raw_bindings.insert(
+            modifier.keysym().to_string(),
+            key_handler(move |_, _| Ok(())),
+        );
+    }
+
     for tag in &TAGS {
         raw_bindings.extend([
             (
-                format!("M-{}", if tag == &"10" { "0" } else { tag }),
+                format!("M-{}", key_for_tag(tag)),
+                key_handler(move |state, x: &RustConn| focus_or_launch_tag(state, x, tag)),
+            ),
+            (
+                format!("M-C-{}", key_for_tag(tag)),
                 key_handler(move |state, x: &RustConn| {
-                    let apps = get_pinned_apps();
-                    if let Some(app) = apps.get(tag) {
-                        if !state
-                            .client_set
-                            .clients()
-                            .any(|client| app.query.run(*client, x).unwrap_or(false))
-                        {
-                            // No client found for this App
-                            util::spawn(app.command)?;
-                            // (No need to refresh because we're not launched yet)
-                            return Ok(());
-                        }
-                    }
-                    if &state.client_set.current_tag() == tag {
-                        // Already focused, cycle through them.
-                        cycle_workspace(state, tag)?;
-                    } else {
-                        state.client_set.focus_tag(tag);
+                    focus_or_cycle_tag(state, x, tag, Direction::Backward)
+                }),
+            ),
+            (
+                format!("M-S-{}", key_for_tag(tag)),
+                key_handler(move |state, x: &RustConn| {
+                    if let Some(client) = state.client_set.current_client().cloned() {
+                        state
+                            .extension_or_default::<ManualPlacements>()
+                            .borrow_mut()
+                            .clients
+                            .insert(client);
+                        state.client_set.move_focused_to_tag(tag);
                     }
                     x.refresh(state)
                 }),
             ),
-            // (
-            //     format!("M-S-{tag}"),
-            //     modify_with(move |client_set| client_set.move_focused_to_tag(tag)),
-            // ),
         ]);
     }
 
+    // Tags past 10 only get created when we run out of room, so these reach
+    // them (or make them) from the F keys.
+    for index in 1..=12 {
+        let tag = (TAGS.len() + index).to_string();
+        raw_bindings.insert(
+            format!("M-F{index}"),
+            key_handler(move |state, x: &RustConn| {
+                if state.client_set.workspace(&tag).is_none() {
+                    create_tag(state, &tag)?;
+                }
+                focus_or_cycle_tag(state, x, &tag, Direction::Forward)
+            }),
+        );
+    }
+
     raw_bindings
 }
 
-#[derive(Debug, Default)]
-struct RecentClients {
-    recent_clients: Vec<Xid>,
-    chronological_clients: Vec<Xid>,
-    switching: bool,
+/// The index of the screen after (or before) the focused one, wrapping around.
+/// `None` if there's only the one screen.
+fn adjacent_screen<X: XConn>(state: &State<X>, direction: &Direction) -> Option<usize> {
+    let screen_count = state.client_set.screens().count();
+    if screen_count < 2 {
+        return None;
+    }
+    let current = state.client_set.current_screen().index();
+    Some(next_position(current, screen_count, direction, true))
 }
 
-#[derive(Debug, Clone)]
-enum Direction {
-    Forward,
-    Backward,
+fn focus_adjacent_screen<X: XConn + 'static>(
+    state: &mut State<X>,
+    x: &X,
+    direction: Direction,
+) -> Result<()> {
+    if let Some(index) = adjacent_screen(state, &direction) {
+        state.client_set.focus_screen(index);
+    }
+    x.refresh(state)
 }
 
-#[derive(Debug, Clone)]
-enum SwitchContext {
-    Workspace,
-    Global,
+/// The next (or previous) screen showing a focused client, and that client,
+/// wrapping around.
+fn next_screen_client(client_set: &ClientSet, direction: &Direction) -> Option<(usize, Xid)> {
+    let screen_count = client_set.screens().count();
+    let mut index = client_set.current_screen().index();
+    for _ in 1..screen_count {
+        index = next_position(index, screen_count, direction, true);
+        let focus = client_set
+            .screens()
+            .find(|screen| screen.index() == index)
+            .and_then(|screen| screen.workspace.focus().cloned());
+        if let Some(client) = focus {
+            return Some((index, client));
+        }
+    }
+
+    None
 }
 
-fn task_switch<X: XConn + 'static>(
+/// Jump to the focused window on the next or previous screen. Unlike
+/// [focus_adjacent_screen] this skips over screens with nothing on them, and
+/// the window it lands on goes to the front of the MRU list like any other.
+fn focus_screen_client<X: XConn + 'static>(
     state: &mut State<X>,
     x: &X,
-    context: SwitchContext,
     direction: Direction,
 ) -> Result<()> {
-    let focus = state.client_set.current_client().cloned();
-    let recent_clients = state.extension_or_default::<RecentClients>();
-    let recent_clients = recent_clients.borrow();
-
-    let clients_on_workspace = match context {
-        SwitchContext::Workspace => state
-            .client_set
-            .current_workspace()
-            .clients()
-            .collect::<HashSet<_>>(),
-        SwitchContext::Global => state.client_set.clients().collect::<HashSet<_>>(),
+    let (index, client) = match next_screen_client(&state.client_set, &direction) {
+        Some(target) => target,
+        None => {
+            debug!("No other screen has a window to focus");
+            return Ok(());
+        }
     };
-    let clients_on_workspace = recent_clients
-        .recent_clients
-        .iter()
-        .filter(|client| clients_on_workspace.contains(client))
-        .cloned()
-        .collect::<Vec<_>>();
-    // Shouldn't really happen, but whatever
-    if clients_on_workspace.is_empty() {
-        return Ok(());
-    }
+    debug!(?client, "Focusing the window on screen {index}");
+    state.client_set.focus_screen(index);
+    state.client_set.focus_client(&client);
 
-    let focused_position = focus
-        .and_then(|focus| {
-            clients_on_workspace
-                .iter()
-                .cloned()
-                .position(|ws| ws == focus)
-        })
-        .unwrap_or(0);
+    x.refresh(state)
+}
 
-    let new_focused_position = match direction {
-        Direction::Forward => (focused_position + 1) % clients_on_workspace.len(),
-        // Wrap around to the end if we're at the start
-        Direction::Backward if focused_position == 0 => clients_on_workspace.len() - 1,
-        // (Otherwise, keep ticking backwards)
-        Direction::Backward => focused_position - 1,
+/// Send the focused client to whatever's showing on the next or previous
+/// screen, and follow it there. If `backfill_gaps` renumbers that workspace
+/// afterwards it pulls the new tag onto the same screen, so the window stays
+/// where it was sent.
+fn move_focused_to_screen<X: XConn + 'static>(
+    state: &mut State<X>,
+    x: &X,
+    direction: Direction,
+) -> Result<()> {
+    let client = match state.client_set.current_client().cloned() {
+        Some(client) => client,
+        None => return Ok(()),
     };
-    println!("New focused position: {new_focused_position} (was: {focused_position})");
-    state
+    let index = match adjacent_screen(state, &direction) {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+    let tag = state
         .client_set
-        .focus_client(&clients_on_workspace[new_focused_position]);
-    std::mem::drop(recent_clients);
-    x.refresh(state)?;
-    Ok(())
+        .screens()
+        .find(|screen| screen.index() == index)
+        .map(|screen| screen.workspace.tag().to_string());
+    if let Some(tag) = tag {
+        debug!(?client, "Moving client to screen {index} ({tag})");
+        state.client_set.move_client_to_tag(&client, &tag);
+        state.client_set.focus_screen(index);
+        state.client_set.focus_client(&client);
+    }
+
+    x.refresh(state)
 }
 
-fn cycle_workspace<X: XConn + 'static>(state: &mut State<X>, tag: &str) -> Result<()> {
-    let workspace = match state.client_set.workspace(tag) {
-        Some(workspace) => workspace,
+/// Pull every window belonging to the focused client's app onto the current
+/// tag, undoing the grouping in [get_tag_for_client]. Pinned apps are left on
+/// their own tags.
+fn gather_app_windows<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let focus = match state.client_set.current_client().cloned() {
+        Some(focus) => focus,
         None => return Ok(()),
     };
-    let focus = workspace.focus().cloned();
-    let recent_clients = state.extension_or_default::<RecentClients>();
-    let recent_clients = recent_clients.borrow();
+    let app_name = match get_app_name(focus, x) {
+        Some(app_name) => app_name,
+        None => return Ok(()),
+    };
+    if get_pinned_apps(state)
+        .values()
+        .any(|app| app.matches(focus, x))
+    {
+        info!("{app_name} is pinned, not gathering its windows");
+        return Ok(());
+    }
 
-    let clients_on_workspace = state
+    let tag = state.client_set.current_tag().to_string();
+    let scratchpad = scratchpad_client(state);
+    let clients = state
         .client_set
-        .workspace(tag)
-        .unwrap()
         .clients()
-        .collect::<HashSet<_>>();
-    let clients_on_workspace = recent_clients
-        .chronological_clients
-        .iter()
-        .filter(|client| clients_on_workspace.contains(client))
+        .filter(|client| {
+            Some(**client) != scratchpad
+                && state
+                    .client_set
+                    .tag_for_client(client)
+                    .map_or(false, |t| t != tag && !is_hidden_tag(t))
+                && get_app_name(**client, x).as_ref() == Some(&app_name)
+        })
         .cloned()
         .collect::<Vec<_>>();
-    // Shouldn't really happen, but whatever
-    if clients_on_workspace.is_empty() {
-        return Ok(());
+    debug!(?clients, "Gathering {app_name} windows onto {tag}");
+    for client in clients {
+        state.client_set.move_client_to_tag(&client, &tag);
     }
+    state.client_set.focus_client(&focus);
 
-    let focused_position = focus
-        .and_then(|focus| {
-            clients_on_workspace
-                .iter()
-                .cloned()
-                .position(|ws| ws == focus)
-        })
-        .unwrap_or(0);
-
-    let new_focused_position = (focused_position + 1) % clients_on_workspace.len();
-    println!("New focused position: {new_focused_position} (was: {focused_position})");
-    state
-        .client_set
-        .focus_client(&clients_on_workspace[new_focused_position]);
-    Ok(())
+    x.refresh(state)
 }
 
-fn move_pinned_windows<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
-    println!(
-        "New window just dropped: {:?}",
-        x.get_prop(client, Atom::WmClass.as_ref()).ok().flatten()
-    );
-    let tag = get_tag_for_client(client, state, x)?;
-    println!("...Tag is {tag}");
-    state.client_set.move_client_to_tag(&client, &tag);
+/// Kill every window belonging to the focused client's app, other than any
+/// tucked away on a hidden tag. The MRU lists get tidied up by
+/// `populate_windows` once they're gone.
+fn kill_app_windows<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let app_name = match state
+        .client_set
+        .current_client()
+        .and_then(|focus| get_app_name(*focus, x))
+    {
+        Some(app_name) => app_name,
+        None => return Ok(()),
+    };
+    let clients = state
+        .client_set
+        .clients()
+        .filter(|client| {
+            state
+                .client_set
+                .tag_for_client(client)
+                .map_or(false, |tag| !is_hidden_tag(tag))
+                && get_app_name(**client, x).as_ref() == Some(&app_name)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    info!(?clients, "Killing all {app_name} windows");
+    let tag = state.client_set.current_tag().to_string();
+    // Go through kill_focused so these are killed just like with M-S-q
+    for client in clients {
+        state.client_set.focus_client(&client);
+        state.client_set.kill_focused();
+    }
+    // Focusing each one brought its tag along, so go back to where we were
     state.client_set.focus_tag(&tag);
-    state.client_set.focus_client(&client);
 
-    Ok(())
+    x.refresh(state)
 }
 
-fn populate_new_window<X: XConn + 'static>(
-    client: Xid,
-    state: &mut State<X>,
-    _x: &X,
-) -> Result<()> {
-    let recent_clients = state.extension_or_default::<RecentClients>();
-    let mut recent_clients = recent_clients.borrow_mut();
-    recent_clients.recent_clients.insert(0, client);
-    recent_clients.chronological_clients.push(client);
-
-    Ok(())
+/// Focus `tag` like [focus_or_cycle_tag], launching its pinned app first if
+/// it isn't running.
+fn focus_or_launch_tag<X: XConn + 'static>(state: &mut State<X>, x: &X, tag: &str) -> Result<()> {
+    let apps = get_pinned_apps(state);
+    if let Some(app) = apps.get(tag) {
+        if !state
+            .client_set
+            .clients()
+            .any(|client| app.matches(*client, x))
+        {
+            // No client found for this App. If we only just launched it, it's
+            // probably still starting up.
+            let now = Instant::now();
+            let pending = state.extension_or_default::<PendingSpawns>();
+            if pending.borrow().recently_spawned(&app.command, now) {
+                debug!("Still waiting on {} to appear", app.command);
+                state.client_set.focus_tag(tag);
+                return x.refresh(state);
+            }
+            // If it won't launch, stay where we are rather than switching to a
+            // tag that's going to stay empty.
+            if let Err(e) = app.spawn() {
+                warn!("Failed to launch {}: {e}", app.command);
+                return Ok(());
+            }
+            pending
+                .borrow_mut()
+                .commands
+                .insert(app.command.clone(), now);
+            // Switch over now so the window shows up where we're looking.
+            // move_pinned_windows focusing the tag again once it's mapped is
+            // harmless.
+            state.client_set.focus_tag(tag);
+            return x.refresh(state);
+        }
+        if let Some(client) = running_elsewhere(state, x, tag, app) {
+            match app.running_elsewhere {
+                RunningElsewhere::Focus => {
+                    debug!(?client, "{} is running elsewhere, going to it", app.command);
+                    if let Some(other) = state.client_set.tag_for_client(&client).map(String::from)
+                    {
+                        state.client_set.focus_tag(&other);
+                    }
+                }
+                RunningElsewhere::Move => {
+                    debug!(
+                        ?client,
+                        "{} is running elsewhere, bringing it back", app.command
+                    );
+                    state.client_set.move_client_to_tag(&client, tag);
+                    state.client_set.focus_tag(tag);
+                }
+            }
+            state.client_set.focus_client(&client);
+            return x.refresh(state);
+        }
+    }
+    focus_or_cycle_tag(state, x, tag, Direction::Forward)
 }
 
-fn get_tag_for_client<X: XConn + 'static>(
-    client: Xid,
+/// The most recently used window of `app`, if it's running but none of its
+/// windows are on its pinned `tag`.
+fn running_elsewhere<X: XConn + 'static>(
     state: &mut State<X>,
     x: &X,
-) -> Result<String> {
-    let pinned_apps = get_pinned_apps();
-    if let Some((tag, _)) = pinned_apps
-        .iter()
-        .find(|(_, app)| app.query.run(client, x).unwrap_or(false))
-    {
-        println!("Belongs to a pinned app :)");
-        return Ok(tag.to_string());
-    }
-    if let Some(app_name) = get_app_name(client, x) {
-        if let Some(workspace) = state.client_set.ordered_workspaces().find(|ws| {
-            ws.clients().any(|existing_client| {
-                get_app_name(*existing_client, x)
-                    .map(|new| app_name == new)
-                    .unwrap_or(false)
-                    && client != *existing_client
-            })
-        }) {
-            println!("App is already open on another workspace");
-            return Ok(workspace.tag().to_string());
-        }
+    tag: &str,
+    app: &PinnedApp<X>,
+) -> Option<Xid> {
+    let on_tag = state.client_set.workspace(tag).map_or(false, |ws| {
+        ws.clients().any(|client| app.matches(*client, x))
+    });
+    if on_tag {
+        return None;
     }
-
-    if let Some(ws) = state
+    let elsewhere = state
         .client_set
-        .ordered_workspaces()
-        .find(|ws| !pinned_apps.contains_key(ws.tag()) && ws.is_empty())
-    {
-        println!("Empty workspace");
-        return Ok(ws.tag().to_string());
-    }
+        .clients()
+        .filter(|client| app.matches(**client, x))
+        .cloned()
+        .collect::<HashSet<_>>();
+    let recent_clients = state.extension_or_default::<RecentClients>();
+    let recent_clients = recent_clients.borrow();
 
-    // Create new if we can't find any other groups:
-    let last_ws_tag = state
-        .client_set
-        .ordered_workspaces()
-        .filter_map(|ws| ws.tag().parse::<i32>().ok())
-        .last()
-        .unwrap_or(0);
+    recent_clients
+        .recent_clients
+        .iter()
+        .find(|client| elsewhere.contains(client))
+        .or_else(|| elsewhere.iter().next())
+        .cloned()
+}
+
+/// The focused tag as of the last refresh, and the one before it.
+#[derive(Debug, Default)]
+struct TagHistory {
+    current: String,
+    previous: Option<String>,
+}
 
-    let new_tag = (last_ws_tag + 1).to_string();
-    create_tag(state, &new_tag)?;
+/// Refresh hook keeping track of the previously focused tag.
+fn track_previous_tag<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    let tag = state.client_set.current_tag().to_string();
+    if is_hidden_tag(&tag) {
+        return Ok(());
+    }
+    let history = state.extension_or_default::<TagHistory>();
+    let mut history = history.borrow_mut();
+    if history.current != tag {
+        let previous = std::mem::replace(&mut history.current, tag);
+        history.previous = Some(previous).filter(|previous| !previous.is_empty());
+    }
 
-    println!("New tag");
-    Ok(new_tag)
+    Ok(())
 }
 
-fn default_layout_factory() -> LayoutStack {
-    LayoutStack::new(VecDeque::default(), Monocle::boxed(), VecDeque::default())
+/// Key handler flipping back to the tag focused before this one.
+fn focus_previous_tag<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let previous = state
+        .extension_or_default::<TagHistory>()
+        .borrow()
+        .previous
+        .clone();
+    match previous {
+        Some(tag) if state.client_set.workspace(&tag).is_some() => {
+            state.client_set.focus_tag(&tag);
+            x.refresh(state)
+        }
+        _ => {
+            debug!("No previous tag to go back to");
+            Ok(())
+        }
+    }
 }
 
-fn create_tag<X: XConn + 'static>(state: &mut State<X>, tag: &str) -> Result<()> {
-    state
-        .client_set
-        .add_workspace(tag, default_layout_factory())
+/// Focus `tag`, or cycle through its windows if we're already there.
+fn focus_or_cycle_tag<X: XConn + 'static>(
+    state: &mut State<X>,
+    x: &X,
+    tag: &str,
+    direction: Direction,
+) -> Result<()> {
+    if state.client_set.current_tag() == tag {
+        // Already focused, cycle through them.
+        cycle_workspace(state, tag, direction)?;
+    } else {
+        state.client_set.focus_tag(tag);
+    }
+    x.refresh(state)
 }
 
-fn backfill_gaps<X: XConn + 'static>(state: &mut State<X>, _x: &X) -> Result<()> {
-    let pinned_apps = get_pinned_apps::<X>();
-    let all_workspaces = state
+#[derive(Debug, Default)]
+struct RecentClients {
+    recent_clients: Vec<Xid>,
+    chronological_clients: Vec<Xid>,
+    switching: bool,
+    /// When we last switched while `switching`, so we can give up waiting for
+    /// an Alt release that got lost along the way.
+    switching_since: Option<Instant>,
+    /// The focus we last moved to the front of `recent_clients`, so that it's
+    /// only done when focus changes and anything moved by hand stays put.
+    committed_focus: Option<Xid>,
+}
+
+/// How long we'll wait in the middle of a task switch before committing it
+/// ourselves.
+const SWITCHING_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl RecentClients {
+    /// Put a newly managed client at the front of the MRU list (or the back,
+    /// if not `to_front`) and the back of the chronological one, taking it out
+    /// of wherever it was before so that a client being remapped doesn't show
+    /// up twice.
+    fn add(&mut self, client: Xid, to_front: bool) {
+        self.recent_clients.retain(|existing| *existing != client);
+        self.chronological_clients
+            .retain(|existing| *existing != client);
+        if to_front {
+            self.recent_clients.insert(0, client);
+        } else {
+            self.recent_clients.push(client);
+        }
+        self.chronological_clients.push(client);
+    }
+
+    /// Drop anything that isn't in `clients`.
+    fn retain_clients(&mut self, clients: &HashSet<Xid>) {
+        self.recent_clients
+            .retain(|client| clients.contains(client));
+        self.chronological_clients
+            .retain(|client| clients.contains(client));
+    }
+
+    /// Move `client` one place towards the front of the MRU list going
+    /// backward, or towards the back going forward. Returns whether it moved.
+    fn shift(&mut self, client: Xid, direction: Direction) -> bool {
+        let position = match self.recent_clients.iter().position(|c| *c == client) {
+            Some(position) => position,
+            None => return false,
+        };
+        let new_position = next_position(position, self.recent_clients.len(), &direction, false);
+        if new_position == position {
+            return false;
+        }
+        self.recent_clients.swap(position, new_position);
+        true
+    }
+
+    fn start_switching(&mut self, now: Instant) {
+        self.switching = true;
+        self.switching_since = Some(now);
+    }
+
+    /// Stop switching if it's been going on for longer than `timeout`.
+    /// Returns whether we gave up.
+    fn expire_switching(&mut self, now: Instant, timeout: Duration) -> bool {
+        match self.switching_since {
+            Some(since) if self.switching && now.duration_since(since) > timeout => {
+                self.switching = false;
+                self.switching_since = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The focused tag as of the last refresh, so we can tell when its last client
+/// has just gone away.
+#[derive(Debug, Default)]
+struct FocusRecovery {
+    tag: String,
+    had_clients: bool,
+}
+
+/// Clients we floated ourselves, which stay out of placement and the MRU lists.
+#[derive(Debug, Default)]
+struct FloatingClients {
+    clients: HashSet<Xid>,
+}
+
+/// Clients the user has moved to a tag by hand, which automatic placement
+/// should leave alone.
+#[derive(Debug, Default)]
+struct ManualPlacements {
+    clients: HashSet<Xid>,
+}
+
+/// Commands for pinned apps that the user asked for and which haven't shown up
+/// yet. Their windows get focused when they do, unlike anything else that
+/// appears in the background.
+#[derive(Debug, Default)]
+struct PendingSpawns {
+    /// When each command was launched
+    commands: HashMap<String, Instant>,
+}
+
+/// How long after launching a pinned app we'll wait for it to show up before
+/// launching it again.
+const SPAWN_GUARD: Duration = Duration::from_secs(5);
+
+impl PendingSpawns {
+    /// Whether `command` was launched within [SPAWN_GUARD] of `now`.
+    fn recently_spawned(&self, command: &str, now: Instant) -> bool {
+        self.commands
+            .get(command)
+            .map_or(false, |spawned| now.duration_since(*spawned) < SPAWN_GUARD)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Forward,
+    Backward,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SwitchContext {
+    Workspace,
+    Global,
+    /// Windows of the same app as the focused one, from any tag
+    SameApp,
+}
+
+/// The clients `task_switch` can move between, in MRU order. Membership comes
+/// from the live client set rather than anything remembered in
+/// [RecentClients], so clients that `backfill_gaps` moved to another tag are
+/// found where they are now.
+fn switch_candidates(
+    client_set: &ClientSet,
+    recent_clients: &[Xid],
+    context: &SwitchContext,
+) -> Vec<Xid> {
+    let clients_in_context = match context {
+        SwitchContext::Workspace => client_set
+            .current_workspace()
+            .clients()
+            .collect::<HashSet<_>>(),
+        // Narrowed down to the app by task_switch, which can look it up
+        SwitchContext::Global | SwitchContext::SameApp => {
+            client_set.clients().collect::<HashSet<_>>()
+        }
+    };
+    recent_clients
+        .iter()
+        .filter(|client| clients_in_context.contains(client))
+        .cloned()
+        .collect()
+}
+
+/// Step from `position` in a list of `len` items, either wrapping around at the
+/// ends or stopping there.
+fn next_position(position: usize, len: usize, direction: &Direction, wrap: bool) -> usize {
+    match direction {
+        Direction::Forward if position + 1 < len => position + 1,
+        // Wrap around to the start if we're at the end
+        Direction::Forward if wrap => 0,
+        Direction::Forward => position,
+        // (Likewise going backwards)
+        Direction::Backward if position > 0 => position - 1,
+        Direction::Backward if wrap => len - 1,
+        Direction::Backward => position,
+    }
+}
+
+/// The client to focus after `focused` when stepping through `clients`, which
+/// are already filtered and in the order to step through them. A focused client
+/// that isn't in the list counts as being at the start.
+fn select_next_client(
+    clients: &[Xid],
+    focused: Option<Xid>,
+    direction: Direction,
+    wrap: bool,
+) -> Option<Xid> {
+    if clients.is_empty() {
+        return None;
+    }
+    let focused_position = focused
+        .and_then(|focused| clients.iter().position(|client| *client == focused))
+        .unwrap_or(0);
+    let new_focused_position = next_position(focused_position, clients.len(), &direction, wrap);
+    debug!("New focused position: {new_focused_position} (was: {focused_position})");
+
+    Some(clients[new_focused_position])
+}
+
+fn task_switch<X: XConn + 'static>(
+    state: &mut State<X>,
+    x: &X,
+    context: SwitchContext,
+    direction: Direction,
+) -> Result<Vec<Xid>> {
+    let _span = debug_span!("task_switch", ?context, ?direction).entered();
+    let focus = state.client_set.current_client().cloned();
+    let scratchpad = scratchpad_client(state);
+    let sticky = sticky::sticky_clients(state);
+    let minimized = minimize::minimized_clients(state);
+    let wrap = switch_config(state).wrap;
+    let recent_clients = state.extension_or_default::<RecentClients>();
+    let recent_clients = recent_clients.borrow();
+
+    let mut clients_on_workspace =
+        switch_candidates(&state.client_set, &recent_clients.recent_clients, &context);
+    // The scratchpad has its own binding, sticky clients are always in view and
+    // minimized ones were put away on purpose
+    clients_on_workspace.retain(|client| {
+        Some(*client) != scratchpad && !sticky.contains(client) && !minimized.contains(client)
+    });
+    if context == SwitchContext::SameApp {
+        let app_name = focus.and_then(|focus| get_app_name(focus, x));
+        clients_on_workspace
+            .retain(|client| app_name.is_some() && get_app_name(*client, x) == app_name);
+    }
+    let next = select_next_client(&clients_on_workspace, focus, direction, wrap);
+    // Shouldn't really happen, but whatever
+    let next = match next {
+        Some(next) => next,
+        None => return Ok(clients_on_workspace),
+    };
+    state.client_set.focus_client(&next);
+    log_focus_history(&recent_clients, x);
+    std::mem::drop(recent_clients);
+    x.refresh(state)?;
+    Ok(clients_on_workspace)
+}
+
+/// Key handler moving the focused client through the MRU list without changing
+/// focus, so alt-tab reaches it sooner or later than it otherwise would.
+fn shift_in_recent_clients<X: XConn + 'static>(
+    state: &mut State<X>,
+    x: &X,
+    direction: Direction,
+) -> Result<()> {
+    let client = match state.client_set.current_client().cloned() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    let recent_clients = state.extension_or_default::<RecentClients>();
+    let mut recent_clients = recent_clients.borrow_mut();
+    if recent_clients.switching {
+        debug!("Not reordering clients in the middle of task switching");
+        return Ok(());
+    }
+    if !recent_clients.shift(client, direction) {
+        return Ok(());
+    }
+    log_focus_history(&recent_clients, x);
+    std::mem::drop(recent_clients);
+
+    x.refresh(state)
+}
+
+/// Flip back to the last window used on this workspace, like tapping alt-tab.
+fn focus_previous_client<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let focus = state.client_set.current_client().cloned();
+    let on_workspace = state
         .client_set
-        .ordered_workspaces()
-        .map(|ws| ws.tag().to_string())
-        .filter(|tag| !pinned_apps.contains_key(tag.as_str()))
+        .current_workspace()
+        .clients()
+        .cloned()
+        .collect::<HashSet<_>>();
+    let recent_clients = state.extension_or_default::<RecentClients>();
+    // The front of the list is the focused client, so this is usually the
+    // second entry.
+    let previous = recent_clients
+        .borrow()
+        .recent_clients
+        .iter()
+        .find(|client| on_workspace.contains(client) && Some(**client) != focus)
+        .cloned();
+
+    match previous {
+        Some(previous) => {
+            state.client_set.focus_client(&previous);
+            x.refresh(state)
+        }
+        None => {
+            debug!("No previous client on this workspace");
+            Ok(())
+        }
+    }
+}
+
+/// Window titles for `clients`, to make logs about them readable.
+fn describe_clients<X: XConn>(clients: &[Xid], x: &X) -> Vec<String> {
+    clients
+        .iter()
+        .map(|client| format!("{client:?}: {}", get_window_title(*client, x)))
+        .collect()
+}
+
+/// Dump both MRU lists to the focus history log, if it's enabled.
+fn log_focus_history<X: XConn>(recent_clients: &RecentClients, x: &X) {
+    // Looking up titles means a round trip per client, so don't bother unless
+    // someone's listening.
+    if !tracing::enabled!(target: FOCUS_LOG_TARGET, Level::DEBUG) {
+        return;
+    }
+    debug!(
+        target: FOCUS_LOG_TARGET,
+        switching = recent_clients.switching,
+        recent = ?describe_clients(&recent_clients.recent_clients, x),
+        chronological = ?describe_clients(&recent_clients.chronological_clients, x),
+    );
+}
+
+fn cycle_workspace<X: XConn + 'static>(
+    state: &mut State<X>,
+    tag: &str,
+    direction: Direction,
+) -> Result<()> {
+    let wrap = switch_config(state).wrap;
+    let workspace = match state.client_set.workspace(tag) {
+        Some(workspace) => workspace,
+        None => return Ok(()),
+    };
+    let focus = workspace.focus().cloned();
+    let recent_clients = state.extension_or_default::<RecentClients>();
+    let recent_clients = recent_clients.borrow();
+
+    let clients_on_workspace = state
+        .client_set
+        .workspace(tag)
+        .unwrap()
+        .clients()
+        .collect::<HashSet<_>>();
+    let clients_on_workspace = recent_clients
+        .chronological_clients
+        .iter()
+        .filter(|client| clients_on_workspace.contains(client))
+        .cloned()
         .collect::<Vec<_>>();
+    let next = select_next_client(&clients_on_workspace, focus, direction, wrap);
+    std::mem::drop(recent_clients);
+    // Shouldn't really happen, but whatever
+    if let Some(next) = next {
+        state.client_set.focus_client(&next);
+    }
+    Ok(())
+}
+
+fn move_pinned_windows<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+    if ClientKind::of(client, state, x).is_exempt() {
+        return Ok(());
+    }
+    if state
+        .extension_or_default::<ManualPlacements>()
+        .borrow()
+        .clients
+        .contains(&client)
+    {
+        debug!("Client was placed by hand, leaving it be");
+        return Ok(());
+    }
+    debug!(
+        "New window just dropped: {:?}",
+        x.get_prop(client, Atom::WmClass.as_ref()).ok().flatten()
+    );
+    let (tag, reason) = get_tag_for_client(client, state, x)?;
+    info!(?client, ?reason, "Placing new client on {tag}");
+
+    let requested = match get_pinned_apps(state).get(&tag) {
+        Some(app) if app.matches(client, x) => state
+            .extension_or_default::<PendingSpawns>()
+            .borrow_mut()
+            .commands
+            .remove(&app.command)
+            .is_some(),
+        _ => false,
+    };
+    // We've already been added to the current workspace at this point
+    let nothing_to_steal_from = state
+        .client_set
+        .current_workspace()
+        .clients()
+        .all(|existing| *existing == client);
+
+    state.client_set.move_client_to_tag(&client, &tag);
+    if requested || nothing_to_steal_from {
+        state.client_set.focus_tag(&tag);
+        state.client_set.focus_client(&client);
+    } else {
+        debug!("...Not stealing focus for a background window");
+    }
+
+    Ok(())
+}
+
+const RESORT_WAKE_ATOM: &str = "_WENDY_RESORT";
+
+/// Startup hook putting existing clients back where they were before we
+/// restarted, and sending pinned apps' windows that drifted off their tags
+/// back there. Existing clients are managed after the startup hooks run, so
+/// this wakes [resort_listener] to do it once they have.
+fn resort_existing_clients<X: XConn + 'static>(_: &mut State<X>, _: &X) -> Result<()> {
+    Waker::new(RESORT_WAKE_ATOM)?.wake()
+}
+
+/// The clients re-sorting may move: those that aren't `settled` already, aren't
+/// exempt from placement going by their `kinds`, and aren't on a hidden tag.
+fn resort_candidates(
+    client_set: &ClientSet,
+    kinds: &HashMap<Xid, ClientKind>,
+    settled: &HashSet<Xid>,
+) -> Vec<Xid> {
+    client_set
+        .clients()
+        .filter(|client| {
+            !settled.contains(client)
+                && !kinds.get(client).map_or(false, ClientKind::is_exempt)
+                && client_set
+                    .tag_for_client(client)
+                    .map_or(false, |tag| !is_hidden_tag(tag))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Which of `clients` are off the pinned tag they belong on, and where they
+/// should go instead.
+fn pinned_relocations(
+    client_set: &ClientSet,
+    clients: &[(Xid, PlacementFacts)],
+) -> Vec<(Xid, String)> {
+    clients
+        .iter()
+        .filter_map(|(client, facts)| {
+            let (tag, reason) = choose_tag(*client, client_set, facts);
+            let current = client_set.tag_for_client(client)?;
+            (reason == PlacementReason::Pinned && current != tag).then_some((*client, tag))
+        })
+        .collect()
+}
+
+/// Where every client is, for putting them back after restarting. Minimized and
+/// stashed clients come last, in the order they were put away.
+fn saved_placements(
+    client_set: &ClientSet,
+    minimized: &[(Xid, String)],
+    stashed: &[Xid],
+    swallowed: &HashMap<Xid, (Xid, String)>,
+) -> Vec<SavedPlacement> {
+    let swallowed_by = swallowed
+        .iter()
+        .map(|(client, (terminal, tag))| (*terminal, (*client, tag.clone())))
+        .collect::<HashMap<_, _>>();
+    let mut placements = client_set
+        .clients()
+        .filter_map(|client| {
+            let tag = client_set.tag_for_client(client)?;
+            if tag == minimize::MINIMIZED_TAG || tag == stash::STASH_TAG {
+                return None;
+            }
+            let (swallowed_by, from) = match swallowed_by.get(client) {
+                Some((by, from)) if tag == swallow::SWALLOW_TAG => (Some(**by), Some(from.clone())),
+                _ => (None, None),
+            };
+            Some(SavedPlacement {
+                id: **client,
+                tag: tag.to_string(),
+                from,
+                swallowed_by,
+            })
+        })
+        .collect::<Vec<_>>();
+    placements.extend(minimized.iter().map(|(client, from)| SavedPlacement {
+        id: **client,
+        tag: minimize::MINIMIZED_TAG.to_string(),
+        from: Some(from.clone()),
+        swallowed_by: None,
+    }));
+    placements.extend(stashed.iter().map(|client| SavedPlacement {
+        id: **client,
+        tag: stash::STASH_TAG.to_string(),
+        from: None,
+        swallowed_by: None,
+    }));
+
+    placements
+}
+
+fn move_to_tag<X: XConn + 'static>(state: &mut State<X>, client: Xid, tag: &str) -> Result<()> {
+    // Tags past the fixed ones only exist while something's on them
+    if state.client_set.workspace(tag).is_none() {
+        create_tag(state, tag)?;
+    }
+    state.client_set.move_client_to_tag(&client, tag);
+    Ok(())
+}
+
+/// Put clients back where [saved_placements] found them before we restarted,
+/// returning the ones that were.
+fn restore_placements<X: XConn + 'static>(
+    state: &mut State<X>,
+    saved: Vec<SavedPlacement>,
+) -> Result<HashSet<Xid>> {
+    let mut restored = HashSet::new();
+    for placement in saved {
+        let client = Xid::from(placement.id);
+        if !state.client_set.contains(&client) {
+            continue;
+        }
+        let from = placement
+            .from
+            .unwrap_or_else(|| state.client_set.current_tag().to_string());
+        debug!(?client, "Restoring client to {}", placement.tag);
+        match placement.tag.as_str() {
+            minimize::MINIMIZED_TAG => minimize::minimize(client, from, state)?,
+            stash::STASH_TAG => stash::stash(client, state)?,
+            SCRATCHPAD_TAG => scratchpad::tuck_away(client, state)?,
+            swallow::SWALLOW_TAG => match placement.swallowed_by.map(Xid::from) {
+                Some(by) if state.client_set.contains(&by) => {
+                    swallow::hide_terminal(client, by, from, state)?
+                }
+                _ => move_to_tag(state, client, &from)?,
+            },
+            tag => move_to_tag(state, client, tag)?,
+        }
+        restored.insert(client);
+    }
+
+    Ok(restored)
+}
+
+/// Event hook doing the re-sorting [resort_existing_clients] set up, in one
+/// batch with a single refresh so that focus isn't dragged around.
+fn resort_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    match event {
+        XEvent::ClientMessage(message) if message.dtype == RESORT_WAKE_ATOM => (),
+        _ => return Ok(true),
+    }
+
+    // Anything we put back is where the user left it, pinned or not
+    let restored = restore_placements(state, persist::take_saved_placements())?;
+    let mut settled = state
+        .extension_or_default::<ManualPlacements>()
+        .borrow()
+        .clients
+        .clone();
+    settled.extend(restored.iter().cloned());
+    let clients = state.client_set.clients().cloned().collect::<Vec<_>>();
+    let kinds = clients
+        .into_iter()
+        .map(|client| (client, ClientKind::of(client, state, x)))
+        .collect::<HashMap<_, _>>();
+    let clients = resort_candidates(&state.client_set, &kinds, &settled)
+        .into_iter()
+        .map(|client| (client, placement_facts(client, state, x)))
+        .collect::<Vec<_>>();
+    let moves = pinned_relocations(&state.client_set, &clients);
+    if moves.is_empty() {
+        debug!("Existing clients are all where they belong");
+        if !restored.is_empty() {
+            x.refresh(state)?;
+        }
+        return Ok(false);
+    }
+
+    for (client, tag) in moves {
+        info!(
+            ?client,
+            "Moving existing client back to its pinned tag {tag}"
+        );
+        state.client_set.move_client_to_tag(&client, &tag);
+    }
+    x.refresh(state)?;
+
+    Ok(false)
+}
+
+fn populate_new_window<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+    if ClientKind::of(client, state, x).is_exempt() {
+        return Ok(());
+    }
+    let to_front = placement_config(state).new_window_to_front;
+    state
+        .extension_or_default::<RecentClients>()
+        .borrow_mut()
+        .add(client, to_front);
+
+    Ok(())
+}
+
+/// Float dialogs and utility windows, along with anything a `[[rule]]` says to
+/// float, where they are rather than tiling them.
+fn float_dialogs<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+    if !wants_floating(client, state, x) {
+        return Ok(());
+    }
+    debug!("Floating dialog {client:?}");
+    let geometry = x.client_geometry(client)?;
+    let screen = state.client_set.current_screen().geometry();
+    state
+        .client_set
+        .float(client, centered_in(geometry.w, geometry.h, screen))?;
+    state
+        .extension_or_default::<FloatingClients>()
+        .borrow_mut()
+        .clients
+        .insert(client);
+
+    Ok(())
+}
+
+/// Float the focused client in the middle of the screen, or put it back in the
+/// layout if it's already floating.
+fn toggle_floating<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = match state.client_set.current_client().cloned() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    // These manage their own floating
+    if scratchpad_client(state) == Some(client) || fullscreen_clients(state).contains(&client) {
+        return Ok(());
+    }
+
+    let floating = state.extension_or_default::<FloatingClients>();
+    let mut floating = floating.borrow_mut();
+    if floating.clients.remove(&client) {
+        debug!(?client, "Tiling client");
+        state.client_set.sink(&client);
+    } else {
+        debug!(?client, "Floating client");
+        let screen = state.client_set.current_screen().geometry();
+        let r = centered_in(screen.w * 2 / 3, screen.h * 2 / 3, screen);
+        state.client_set.float(client, r)?;
+        floating.clients.insert(client);
+    }
+    std::mem::drop(floating);
+
+    x.refresh(state)
+}
+
+/// Clients that don't count towards a workspace being occupied.
+fn ignored_clients<X: XConn + 'static>(state: &mut State<X>) -> HashSet<Xid> {
+    let mut ignored = state
+        .extension_or_default::<FloatingClients>()
+        .borrow()
+        .clients
+        .clone();
+    ignored.extend(scratchpad_client(state));
+    ignored.extend(sticky::sticky_clients(state));
+    ignored
+}
+
+/// Whether `ws` has nothing on it other than `ignored` clients.
+fn is_effectively_empty(ws: &Workspace<Xid>, ignored: &HashSet<Xid>) -> bool {
+    ws.clients().all(|client| ignored.contains(client))
+}
+
+/// Tags that never get shown and which automatic placement should leave alone:
+/// the scratchpad's, the overview's, and those holding swallowed, minimized and
+/// stashed clients.
+fn is_hidden_tag(tag: &str) -> bool {
+    tag == SCRATCHPAD_TAG
+        || tag == expose::EXPOSE_TAG
+        || tag == swallow::SWALLOW_TAG
+        || tag == minimize::MINIMIZED_TAG
+        || tag == stash::STASH_TAG
+}
+
+/// What we know about a new client that can exempt it from automatic placement
+/// and the MRU lists.
+#[derive(Debug, Default, Clone, Copy)]
+struct ClientKind {
+    /// One of our own windows
+    internal: bool,
+    scratchpad: bool,
+    /// A dialog or similar, or anything else that gets floated
+    floating_type: bool,
+}
+
+impl ClientKind {
+    fn of<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> Self {
+        Self {
+            internal: internal::is_wm_internal(client, x),
+            scratchpad: is_scratchpad(client, x),
+            floating_type: wants_floating(client, state, x),
+        }
+    }
+
+    fn is_exempt(&self) -> bool {
+        self.internal || self.scratchpad || self.floating_type
+    }
+}
+
+/// Which of the branches in [get_tag_for_client] picked a client's tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlacementReason {
+    /// The client belongs to a pinned app
+    Pinned,
+    /// Another client from the same app is already on the tag
+    GroupedWithExisting,
+    /// The tag was the first free one
+    EmptyWorkspace,
+    /// Everything was taken so we made a new tag
+    NewTag,
+}
+
+/// What placing a new client depends on, read from X and our extensions up
+/// front so that [choose_tag] only needs the client set.
+#[derive(Debug, Default)]
+struct PlacementFacts {
+    /// The tag of the pinned app the client belongs to
+    pinned_tag: Option<String>,
+    /// Tags belonging to pinned apps, which nothing else goes on
+    pinned_tags: HashSet<String>,
+    max_clients: Option<usize>,
+    /// Other clients from the same app
+    group: HashSet<Xid>,
+    /// Clients that don't count towards a workspace being occupied
+    ignored: HashSet<Xid>,
+}
+
+fn get_tag_for_client<X: XConn + 'static>(
+    client: Xid,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<(String, PlacementReason)> {
+    let facts = placement_facts(client, state, x);
+    let (tag, reason) = choose_tag(client, &state.client_set, &facts);
+    if reason == PlacementReason::NewTag {
+        create_tag(state, &tag)?;
+    }
+
+    Ok((tag, reason))
+}
+
+/// Look up everything [choose_tag] needs to know about `client`.
+fn placement_facts<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> PlacementFacts {
+    let pinned_apps = get_pinned_apps(state);
+    let max_clients = placement_config(state).max_clients;
+    // Apps spanning several tags go to the first one that wants the client
+    let pinned = pinned_apps
+        .iter()
+        .filter(|(_, app)| app.matches(client, x))
+        .min_by_key(|(tag, _)| tag_order(tag));
+    // Sticky clients are everywhere, so they don't say anything about where
+    // their app lives
+    let sticky = sticky::sticky_clients(state);
+    let group = match get_group_key(client, x) {
+        Some(group_key) => state
+            .client_set
+            .clients()
+            .filter(|existing| {
+                **existing != client
+                    && !sticky.contains(existing)
+                    && get_group_key(**existing, x).as_ref() == Some(&group_key)
+            })
+            .cloned()
+            .collect(),
+        None => HashSet::new(),
+    };
+    PlacementFacts {
+        pinned_tag: pinned.map(|(tag, _)| tag.clone()),
+        pinned_tags: pinned_apps.keys().cloned().collect(),
+        max_clients: pinned.and_then(|(_, app)| app.max_clients).or(max_clients),
+        group,
+        ignored: ignored_clients(state),
+    }
+}
+
+/// Pick the tag for `client`, which has already been added to the focused
+/// workspace. A [PlacementReason::NewTag] still needs creating.
+fn choose_tag(
+    client: Xid,
+    client_set: &ClientSet,
+    facts: &PlacementFacts,
+) -> (String, PlacementReason) {
+    // We've already been added to the current workspace, so we don't count
+    let is_full = |ws: &Workspace<Xid>| {
+        facts.max_clients.map_or(false, |max| {
+            ws.clients()
+                .filter(|c| **c != client && !facts.ignored.contains(c))
+                .count()
+                >= max
+        })
+    };
+
+    if let Some(tag) = &facts.pinned_tag {
+        match client_set.workspace(tag) {
+            Some(ws) if is_full(ws) => debug!("Pinned tag {tag} is full, overflowing"),
+            _ => {
+                debug!("Belongs to a pinned app :)");
+                return (tag.clone(), PlacementReason::Pinned);
+            }
+        }
+    }
+    if let Some(workspace) = client_set
+        .ordered_workspaces()
+        .find(|ws| !is_full(ws) && ws.clients().any(|c| facts.group.contains(c)))
+    {
+        debug!("App is already open on another workspace");
+        return (
+            workspace.tag().to_string(),
+            PlacementReason::GroupedWithExisting,
+        );
+    }
+
+    if let Some(ws) = client_set.ordered_workspaces().find(|ws| {
+        !facts.pinned_tags.contains(ws.tag())
+            && !is_hidden_tag(ws.tag())
+            && is_effectively_empty(ws, &facts.ignored)
+    }) {
+        debug!("Empty workspace");
+        return (ws.tag().to_string(), PlacementReason::EmptyWorkspace);
+    }
+
+    // Create new if we can't find any other groups:
+    let last_ws_tag = client_set
+        .ordered_workspaces()
+        .filter_map(|ws| ws.tag().parse::<i32>().ok())
+        .max()
+        .unwrap_or(0);
+
+    debug!("New tag");
+    ((last_ws_tag + 1).to_string(), PlacementReason::NewTag)
+}
+
+fn get_appearance() -> Appearance {
+    let path = config::config_path();
+    config::load_appearance(&path).unwrap_or_else(|e| {
+        warn!("Failed to load appearance from {}: {e}", path.display());
+        Appearance::default()
+    })
+}
+
+/// Monocle only has the one window, so there's only an outer gap.
+fn monocle(appearance: &Appearance) -> Box<dyn Layout> {
+    Gaps::wrap(Monocle::boxed(), appearance.outer_gap, 0)
+}
+
+fn grid(appearance: &Appearance) -> Box<dyn Layout> {
+    Gaps::wrap(Grid::boxed(), appearance.outer_gap, appearance.inner_gap)
+}
+
+/// The name of the layout `kind` refers to, e.g. to pass to
+/// `set_layout_by_name`.
+fn layout_kind_name(kind: LayoutKind) -> String {
+    let appearance = Appearance::default();
+    match kind {
+        LayoutKind::Monocle => monocle(&appearance).name(),
+        LayoutKind::MainAndStack => main_and_stack(&appearance).name(),
+        LayoutKind::Grid => grid(&appearance).name(),
+    }
+}
+
+/// The overflow config straight from the config file. Once we're running it's
+/// kept by [overflow::overflow_config] instead.
+fn get_overflow_config() -> config::OverflowConfig {
+    let path = config::config_path();
+    config::load_overflow_config(&path).unwrap_or_else(|e| {
+        warn!(
+            "Failed to load overflow config from {}: {e}",
+            path.display()
+        );
+        config::OverflowConfig::default()
+    })
+}
+
+fn main_and_stack(appearance: &Appearance) -> Box<dyn Layout> {
+    Gaps::wrap(
+        MainAndStack::boxed_default(),
+        appearance.outer_gap,
+        appearance.inner_gap,
+    )
+}
+
+/// `_MOTIF_WM_HINTS` flag saying the decorations field is set.
+const MWM_HINTS_DECORATIONS: u32 = 1 << 1;
+
+/// Whether `client` has asked not to be decorated through `_MOTIF_WM_HINTS`.
+fn wants_no_decorations<X: XConn>(client: Xid, x: &X) -> bool {
+    // flags, functions, decorations, input mode, status
+    let hints = match x.get_prop(client, "_MOTIF_WM_HINTS").ok().flatten() {
+        Some(Prop::Cardinal(hints)) | Some(Prop::Bytes(hints)) => hints,
+        _ => return false,
+    };
+    matches!(hints.as_slice(), [flags, _, 0, ..] if flags & MWM_HINTS_DECORATIONS != 0)
+}
+
+/// Clients that asked for no decorations, which never get a border.
+#[derive(Debug, Default)]
+struct BorderlessClients {
+    clients: HashSet<Xid>,
+}
+
+/// Manage hook picking up clients that don't want a border.
+fn record_borderless<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+    if !wants_no_decorations(client, x) {
+        return Ok(());
+    }
+    debug!(?client, "Client asked for no decorations");
+    let borderless = state.extension_or_default::<BorderlessClients>();
+    let mut borderless = borderless.borrow_mut();
+    borderless
+        .clients
+        .retain(|existing| state.client_set.contains(existing));
+    borderless.clients.insert(client);
+
+    x.set_client_config(client, &[ClientConfig::BorderPx(0)])
+}
+
+/// The client last given its app's border colour, which needs the normal one
+/// putting back once it loses focus.
+#[derive(Debug, Default)]
+struct AppBorder {
+    client: Option<Xid>,
+}
+
+/// Refresh hook giving the focused client its pinned app's border colour, if it
+/// has one. Everything else keeps the usual colours.
+fn app_border_colors<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let focused = state.client_set.current_client().cloned();
+    let previous = state.extension_or_default::<AppBorder>().borrow().client;
+    if let Some(previous) = previous {
+        if Some(previous) != focused && state.client_set.contains(&previous) {
+            let normal = state.config.normal_border.argb_u32();
+            x.set_client_attributes(previous, &[ClientAttr::BorderColor(normal)])?;
+        }
+    }
+
+    let apps = get_pinned_apps(state);
+    let colored = match focused {
+        Some(client) => match apps
+            .values()
+            .find_map(|app| app.border.filter(|_| app.matches(client, x)))
+        {
+            Some(color) => {
+                x.set_client_attributes(client, &[ClientAttr::BorderColor(color.argb_u32())])?;
+                Some(client)
+            }
+            None => None,
+        },
+        None => None,
+    };
+    state
+        .extension_or_default::<AppBorder>()
+        .borrow_mut()
+        .client = colored;
+
+    Ok(())
+}
+
+/// The border width each client was last given by [smart_borders].
+#[derive(Debug, Default)]
+struct BorderWidths {
+    applied: HashMap<Xid, u32>,
+}
+
+impl ForgetClient for BorderWidths {
+    fn forget_client(&mut self, client: Xid) {
+        self.applied.remove(&client);
+    }
+}
+
+/// Refresh hook dropping the border on any screen showing just the one tiled
+/// window, since there's nothing to tell it apart from. Fullscreen clients and
+/// those asking for no decorations never get one, and floating ones always do.
+fn smart_borders<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let border_width = state.config.border_width;
+    let ignored = ignored_clients(state);
+    let fullscreen = fullscreen::fullscreen_clients(state);
+    let mut borderless = state
+        .extension_or_default::<BorderlessClients>()
+        .borrow()
+        .clients
+        .clone();
+    borderless.extend(fullscreen.iter().cloned());
+    let widths = state.extension_or_default::<BorderWidths>();
+    let mut widths = widths.borrow_mut();
+    for screen in state.client_set.screens() {
+        let tiled_count = screen
+            .workspace
+            .clients()
+            .filter(|client| !ignored.contains(client) && !fullscreen.contains(client))
+            .count();
+        for client in screen.workspace.clients() {
+            let alone = tiled_count == 1 && !ignored.contains(client);
+            let width = if borderless.contains(client) || alone {
+                0
+            } else {
+                border_width
+            };
+            if widths.applied.get(client) == Some(&width) {
+                continue;
+            }
+            x.set_client_config(*client, &[ClientConfig::BorderPx(width)])?;
+            widths.applied.insert(*client, width);
+        }
+    }
+
+    Ok(())
+}
+
+/// Key handler putting the focused workspace's layouts back to their defaults,
+/// undoing any changes to the main area's size and count while staying on
+/// the same layout.
+fn reset_layout<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let tag = state.client_set.current_tag().to_string();
+    let layouts = workspace_layouts(state, &tag);
+    if let Some(workspace) = state.client_set.workspace_mut(&tag) {
+        let layout = workspace.layout_name();
+        workspace.set_available_layouts(layouts);
+        workspace.set_layout_by_name(&layout);
+        debug!("Reset the layouts on {tag}, keeping {layout}");
+    }
+
+    x.refresh(state)
+}
+
+/// Workspaces that have had their gaps turned off with `M-S-g`.
+#[derive(Debug, Default)]
+struct Gapless {
+    everywhere: bool,
+    tags: HashSet<String>,
+}
+
+impl Gapless {
+    fn has_gaps(&self, tag: &str) -> bool {
+        !self.everywhere && !self.tags.contains(tag)
+    }
+
+    /// Windows moving from `from` to `to` take their layouts with them, so
+    /// the lack of gaps needs to go too.
+    fn follow(&mut self, from: &str, to: &str) {
+        self.tags.remove(to);
+        if self.tags.remove(from) {
+            self.tags.insert(to.to_string());
+        }
+    }
+}
+
+/// The layouts `tag` should have, without gaps if they've been turned off.
+fn workspace_layouts<X: XConn + 'static>(state: &mut State<X>, tag: &str) -> LayoutStack {
+    let mut appearance = get_appearance();
+    if !state
+        .extension_or_default::<Gapless>()
+        .borrow()
+        .has_gaps(tag)
+    {
+        appearance.outer_gap = 0;
+        appearance.inner_gap = 0;
+    }
+    let overflow = overflow::overflow_config(state).layout;
+    layouts_for_tag(tag, &appearance, overflow)
+}
+
+/// Key handler turning gaps off, or back on, for the focused workspace or all
+/// of them, keeping each workspace on the layout it's using.
+fn toggle_gaps<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let tag = state.client_set.current_tag().to_string();
+    let gapless = state.extension_or_default::<Gapless>();
+    let tags = if get_appearance().toggle_gaps_everywhere {
+        let mut gapless = gapless.borrow_mut();
+        // Going by the focused workspace, so that mixed gaps all end up off
+        gapless.everywhere = gapless.has_gaps(&tag);
+        gapless.tags.clear();
+        state
+            .client_set
+            .workspaces()
+            .map(|ws| ws.tag().to_string())
+            .collect()
+    } else {
+        let mut gapless = gapless.borrow_mut();
+        if gapless.everywhere {
+            // Everything else keeps its lack of gaps
+            gapless.everywhere = false;
+            gapless.tags = state
+                .client_set
+                .workspaces()
+                .map(|ws| ws.tag().to_string())
+                .filter(|t| *t != tag)
+                .collect();
+        } else if !gapless.tags.remove(&tag) {
+            gapless.tags.insert(tag.clone());
+        }
+        vec![tag]
+    };
+
+    for tag in tags {
+        let layouts = workspace_layouts(state, &tag);
+        if let Some(workspace) = state.client_set.workspace_mut(&tag) {
+            let layout = workspace.layout_name();
+            workspace.set_available_layouts(layouts);
+            workspace.set_layout_by_name(&layout);
+        }
+    }
+
+    x.refresh(state)
+}
+
+/// Which clients are the main app, decided when each is first seen so that
+/// refreshes don't keep asking X. Cleared when the config is reloaded.
+#[derive(Debug, Default)]
+struct MainAppClients {
+    matches: HashMap<Xid, bool>,
+}
+
+impl ForgetClient for MainAppClients {
+    fn forget_client(&mut self, client: Xid) {
+        self.matches.remove(&client);
+    }
+}
+
+fn is_main_app<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> bool {
+    let main_app_clients = state.extension_or_default::<MainAppClients>();
+    if let Some(known) = main_app_clients.borrow().matches.get(&client) {
+        return *known;
+    }
+    let is_main_app = get_main_app(state)
+        .iter()
+        .any(|query| query.run(client, x).unwrap_or(false));
+    main_app_clients
+        .borrow_mut()
+        .matches
+        .insert(client, is_main_app);
+    is_main_app
+}
+
+/// Refresh hook moving the configured main app into the main area of the
+/// focused workspace, if it isn't there already. Monocle only shows one window
+/// at a time anyway, so it's left alone. This only touches the client set and
+/// is composed before the border and opacity hooks, so the refresh that's
+/// running carries the swap along with it.
+fn keep_main_app<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    if get_main_app(state).is_empty() {
+        return Ok(());
+    }
+    let workspace = state.client_set.current_workspace();
+    if workspace.layout_name() == layout_kind_name(LayoutKind::Monocle) {
+        return Ok(());
+    }
+    let clients = workspace.clients().cloned().collect::<Vec<_>>();
+    if clients
+        .first()
+        .map_or(true, |head| is_main_app(*head, state, x))
+    {
+        return Ok(());
+    }
+    let main_app = match clients
+        .into_iter()
+        .find(|client| is_main_app(*client, state, x))
+    {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+
+    debug!(client = ?main_app, "Moving the main app into the main area");
+    let focus = state.client_set.current_client().cloned();
+    state.client_set.focus_client(&main_app);
+    state.client_set.swap_focus_and_head();
+    if let Some(focus) = focus {
+        state.client_set.focus_client(&focus);
+    }
+
+    Ok(())
+}
+
+/// Layouts for the hidden tags. These never show on a screen, so they never
+/// need an overflow layout either.
+fn default_layout_factory() -> LayoutStack {
+    default_layouts(&get_appearance(), LayoutKind::MainAndStack)
+}
+
+fn default_layouts(appearance: &Appearance, overflow: LayoutKind) -> LayoutStack {
+    LayoutStack::new(
+        VecDeque::default(),
+        monocle(appearance),
+        VecDeque::from(with_overflow_layout(
+            vec![main_and_stack(appearance)],
+            appearance,
+            overflow,
+        )),
+    )
+}
+
+/// `layouts` along with the grid, if crowded Monocle workspaces switch to it,
+/// so that there's a grid to switch to.
+fn with_overflow_layout(
+    mut layouts: Vec<Box<dyn Layout>>,
+    appearance: &Appearance,
+    overflow: LayoutKind,
+) -> Vec<Box<dyn Layout>> {
+    if overflow == LayoutKind::Grid {
+        layouts.push(grid(appearance));
+    }
+    layouts
+}
+
+/// Layouts for tags that shouldn't start out as Monocle when there is no
+/// config file saying otherwise.
+fn default_tag_layouts() -> HashMap<String, LayoutKind> {
+    HashMap::from([("3".to_string(), LayoutKind::MainAndStack)])
+}
+
+/// Layouts for `tag` straight from the config file, for setting up the initial
+/// tags. Later on [workspace_layouts] goes by what we have loaded.
+fn layout_for_tag(tag: &str) -> LayoutStack {
+    layouts_for_tag(tag, &get_appearance(), get_overflow_config().layout)
+}
+
+fn layouts_for_tag(tag: &str, appearance: &Appearance, overflow: LayoutKind) -> LayoutStack {
+    let path = config::config_path();
+    let layouts = config::load_tag_layouts(&path).unwrap_or_else(|e| {
+        warn!("Failed to load tag layouts from {}: {e}", path.display());
+        default_tag_layouts()
+    });
+    match layouts.get(tag) {
+        Some(LayoutKind::MainAndStack) => LayoutStack::new(
+            VecDeque::default(),
+            main_and_stack(appearance),
+            VecDeque::from(with_overflow_layout(
+                vec![monocle(appearance)],
+                appearance,
+                overflow,
+            )),
+        ),
+        Some(LayoutKind::Grid) => LayoutStack::new(
+            VecDeque::default(),
+            grid(appearance),
+            VecDeque::from([monocle(appearance), main_and_stack(appearance)]),
+        ),
+        Some(LayoutKind::Monocle) | None => default_layouts(appearance, overflow),
+    }
+}
+
+fn create_tag<X: XConn + 'static>(state: &mut State<X>, tag: &str) -> Result<()> {
+    let layouts = workspace_layouts(state, tag);
+    state.client_set.add_workspace(tag, layouts)
+}
+
+/// `config.default_layouts` is shared by every tag, so give the initial tags
+/// their own layouts once they exist.
+fn apply_tag_layouts<X: XConn + 'static>(state: &mut State<X>, _x: &X) -> Result<()> {
+    for tag in TAGS {
+        if let Some(workspace) = state.client_set.workspace_mut(tag) {
+            // Don't clobber anything that's already been arranged
+            if workspace.is_empty() {
+                workspace.set_available_layouts(layout_for_tag(tag));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How long refreshes need to stop for before we compact workspaces again.
+const BACKFILL_DEBOUNCE: Duration = Duration::from_millis(150);
+const BACKFILL_WAKE_ATOM: &str = "_WENDY_BACKFILL";
+
+/// Bookkeeping for coalescing bursts of refreshes into a single compaction.
+#[derive(Debug, Default)]
+struct BackfillDebounce {
+    waker: Option<Waker>,
+    last_run: Option<Instant>,
+    scheduled: bool,
+}
+
+fn start_backfill_debounce<X: XConn + 'static>(state: &mut State<X>, _x: &X) -> Result<()> {
+    state.add_extension(BackfillDebounce {
+        waker: Some(Waker::new(BACKFILL_WAKE_ATOM)?),
+        ..Default::default()
+    });
+    Ok(())
+}
+
+/// Refresh hook running [backfill_gaps], unless we only just did. Refreshes
+/// that come in too quickly get coalesced into one last compaction once
+/// things settle down, so an app opening a bunch of windows at once doesn't
+/// have them shuffled around over and over.
+fn debounced_backfill_gaps<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let debounce = state.extension_or_default::<BackfillDebounce>();
+    let mut debounce = debounce.borrow_mut();
+    let now = Instant::now();
+    let recently_ran = debounce
+        .last_run
+        .map(|last_run| now.duration_since(last_run) < BACKFILL_DEBOUNCE)
+        .unwrap_or(false);
+
+    if recently_ran {
+        match debounce.waker.clone() {
+            Some(waker) if !debounce.scheduled => {
+                debounce.scheduled = true;
+                std::thread::spawn(move || {
+                    std::thread::sleep(BACKFILL_DEBOUNCE);
+                    if let Err(e) = waker.wake() {
+                        warn!("Failed to schedule workspace compaction: {e}");
+                    }
+                });
+            }
+            _ => (),
+        }
+        return Ok(());
+    }
+
+    debounce.last_run = Some(now);
+    std::mem::drop(debounce);
+    backfill_gaps(state, x)
+}
+
+/// Event hook running the compaction that [debounced_backfill_gaps] put off.
+fn backfill_gaps_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    match event {
+        XEvent::ClientMessage(message) if message.dtype == BACKFILL_WAKE_ATOM => (),
+        _ => return Ok(true),
+    }
+    {
+        let debounce = state.extension_or_default::<BackfillDebounce>();
+        let mut debounce = debounce.borrow_mut();
+        debounce.scheduled = false;
+        debounce.last_run = None;
+    }
+    x.refresh(state)?;
+
+    Ok(false)
+}
+
+/// Sort key putting numeric tags in numeric order.
+fn tag_order(tag: &str) -> u32 {
+    tag.parse().unwrap_or(u32::MAX)
+}
+
+fn backfill_gaps<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let pinned_apps = get_pinned_apps(state);
+    let scratchpad = scratchpad_client(state);
+    let ignored = ignored_clients(state);
+    // Workspaces with a fullscreen client stay put, as if they were pinned
+    let fullscreen = fullscreen_clients(state);
+    let fixed = |ws: &Workspace<Xid>| {
+        pinned_apps.contains_key(ws.tag())
+            || is_hidden_tag(ws.tag())
+            || ws.clients().any(|c| fullscreen.contains(c))
+    };
+    let moves = backfill_moves(&state.client_set, fixed, &ignored);
 
     let screens = state
         .client_set
         .screens()
         .cloned()
         .collect::<Vec<Screen<_>>>();
-    let non_empty_workspaces = state
-        .client_set
-        .ordered_workspaces()
-        .filter(|ws| !pinned_apps.contains_key(ws.tag()) && !ws.is_empty())
-        .map(|ws| ws.tag().to_string())
-        .collect::<Vec<_>>();
-
     let current_screen_index = state.client_set.current_screen().index();
-    for (index, old_tag) in non_empty_workspaces.iter().enumerate() {
+    for (old_tag, new_tag) in &moves {
         let current_screen_workspace_tag = state
             .client_set
             .current_screen()
@@ -381,61 +2261,254 @@ fn backfill_gaps<X: XConn + 'static>(state: &mut State<X>, _x: &X) -> Result<()>
             .tag()
             .to_string();
 
-        // All workspaces
-        let new_tag = &all_workspaces[index];
-        if new_tag != old_tag {
-            println!("Moving {old_tag} windows -> {new_tag}");
-            let old_workspace = state.client_set.workspace_mut(old_tag).unwrap();
-            let old_layouts = old_workspace.set_available_layouts(LayoutStack::default());
-            let old_layout = old_workspace.layout_name();
-            let old_workspace_clients = old_workspace.clients().cloned().collect::<Vec<_>>();
-            let screen = screens
-                .iter()
-                .find(|screen| screen.workspace.id() == old_workspace.id())
-                .map(|screen| (screen.index(), screen.workspace.tag()));
-            let focused = old_workspace.focus().cloned();
-            for client in old_workspace_clients.iter() {
-                state.client_set.move_client_to_tag(client, new_tag);
-            }
+        debug!("Moving {old_tag} windows -> {new_tag}");
+        // The emptied workspace goes back to its own default so that the
+        // user's layout choice (gaps included) follows the windows to
+        // `new_tag`.
+        state
+            .extension_or_default::<Gapless>()
+            .borrow_mut()
+            .follow(old_tag, new_tag);
+        let emptied_layouts = workspace_layouts(state, old_tag);
+        let old_workspace = state.client_set.workspace_mut(old_tag).unwrap();
+        let old_layouts = old_workspace.set_available_layouts(emptied_layouts);
+        let old_layout = old_workspace.layout_name();
+        let old_workspace_clients = old_workspace
+            .clients()
+            .filter(|client| Some(**client) != scratchpad && !internal::is_wm_internal(**client, x))
+            .cloned()
+            .collect::<Vec<_>>();
+        let screen = screens
+            .iter()
+            .find(|screen| screen.workspace.id() == old_workspace.id())
+            .map(|screen| (screen.index(), screen.workspace.tag()));
+        let focused = old_workspace.focus().cloned();
+        for client in old_workspace_clients.iter() {
+            state.client_set.move_client_to_tag(client, new_tag);
+        }
 
-            let new_workspace = state.client_set.workspace_mut(new_tag).unwrap();
-            new_workspace.set_available_layouts(old_layouts);
-            new_workspace.set_layout_by_name(&old_layout);
-            if let Some((screen, screen_tag)) = screen {
-                state.client_set.focus_screen(screen);
-                state.client_set.pull_tag_to_screen(new_tag);
-                if screen_tag != old_tag {
-                    state.client_set.focus_tag(screen_tag);
-                }
-                if let Some(focused) = focused {
-                    state.client_set.focus_client(&focused);
-                }
-                state.client_set.focus_screen(current_screen_index);
-                if &current_screen_workspace_tag != old_tag {
-                    state.client_set.focus_tag(&current_screen_workspace_tag);
-                }
+        let new_workspace = state.client_set.workspace_mut(new_tag).unwrap();
+        new_workspace.set_available_layouts(old_layouts);
+        new_workspace.set_layout_by_name(&old_layout);
+        if let Some((screen, screen_tag)) = screen {
+            state.client_set.focus_screen(screen);
+            state.client_set.pull_tag_to_screen(new_tag);
+            if screen_tag != old_tag {
+                state.client_set.focus_tag(screen_tag);
+            }
+            if let Some(focused) = focused {
+                state.client_set.focus_client(&focused);
+            }
+            state.client_set.focus_screen(current_screen_index);
+            if &current_screen_workspace_tag != old_tag {
+                state.client_set.focus_tag(&current_screen_workspace_tag);
             }
         }
     }
     Ok(())
 }
 
-fn populate_windows<X: XConn + 'static>(state: &mut State<X>, _x: &X) -> Result<()> {
-    let all_clients = state.client_set.clients().cloned().collect::<HashSet<_>>();
-    let recent_clients = state.extension_or_default::<RecentClients>();
-    let mut recent_clients = recent_clients.borrow_mut();
-    recent_clients.recent_clients = recent_clients
-        .recent_clients
-        .iter()
-        .filter(|client| all_clients.contains(client))
-        .cloned()
+/// The moves [backfill_gaps] makes, pairing each occupied workspace with the
+/// lowest tag still free for it. Workspaces that are `fixed` in place neither
+/// move nor get moved onto.
+fn backfill_moves(
+    client_set: &ClientSet,
+    fixed: impl Fn(&Workspace<Xid>) -> bool,
+    ignored: &HashSet<Xid>,
+) -> Vec<(String, String)> {
+    let mut all_workspaces = client_set
+        .ordered_workspaces()
+        .filter(|ws| !fixed(ws))
+        .map(|ws| ws.tag().to_string())
         .collect::<Vec<_>>();
-    recent_clients.chronological_clients = recent_clients
-        .chronological_clients
+    // Workspaces are ordered by when they were created, and the F keys can
+    // create higher tags out of order.
+    all_workspaces.sort_by_key(|tag| tag_order(tag));
+
+    let mut non_empty_workspaces = client_set
+        .ordered_workspaces()
+        .filter(|ws| !fixed(ws) && !is_effectively_empty(ws, ignored))
+        .map(|ws| ws.tag().to_string())
+        .collect::<Vec<_>>();
+    non_empty_workspaces.sort_by_key(|tag| tag_order(tag));
+
+    non_empty_workspaces
+        .into_iter()
+        .zip(all_workspaces)
+        .filter(|(old_tag, new_tag)| old_tag != new_tag)
+        .collect()
+}
+
+/// The client to focus when nothing is, or `None` to leave things as they are.
+/// If the focused workspace still has clients the most recent of them is
+/// picked, and if it was `emptied` by its last client going away we move on to
+/// the most recent client anywhere. Empty tags the user went to on purpose
+/// are left alone.
+fn recovery_target(client_set: &ClientSet, recent_clients: &[Xid], emptied: bool) -> Option<Xid> {
+    if client_set.current_client().is_some() {
+        return None;
+    }
+    let workspace = client_set.current_workspace();
+    if workspace.clients().next().is_some() {
+        return recent_clients
+            .iter()
+            .find(|client| workspace.clients().any(|c| c == *client))
+            .or_else(|| workspace.clients().next())
+            .cloned();
+    }
+    if !emptied {
+        return None;
+    }
+
+    recent_clients
         .iter()
-        .filter(|client| all_clients.contains(client))
+        .find(|client| {
+            client_set
+                .tag_for_client(client)
+                .map_or(false, |tag| !is_hidden_tag(tag))
+        })
         .cloned()
-        .collect::<Vec<_>>();
+}
+
+/// Refresh hook making sure closing a window doesn't leave nothing focused.
+/// This runs after [populate_windows] so the MRU list is up to date, and after
+/// `backfill_gaps` has settled where everything is, so the two don't fight
+/// over where focus ends up.
+fn recover_focus<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let tag = state.client_set.current_tag().to_string();
+    let has_clients = state
+        .client_set
+        .current_workspace()
+        .clients()
+        .next()
+        .is_some();
+    let emptied = {
+        let recovery = state.extension_or_default::<FocusRecovery>();
+        let mut recovery = recovery.borrow_mut();
+        let emptied = recovery.tag == tag && recovery.had_clients && !has_clients;
+        recovery.tag = tag;
+        recovery.had_clients = has_clients;
+        emptied
+    };
+    let recent_clients = state
+        .extension_or_default::<RecentClients>()
+        .borrow()
+        .recent_clients
+        .clone();
+
+    let client = match recovery_target(&state.client_set, &recent_clients, emptied) {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    debug!(?client, "Nothing focused, recovering focus");
+    if let Some(tag) = state.client_set.tag_for_client(&client).map(String::from) {
+        state.client_set.focus_tag(&tag);
+    }
+    state.client_set.focus_client(&client);
+
+    x.refresh(state)
+}
+
+/// Extensions keeping track of clients, which need to let go of them once
+/// they're gone.
+trait ForgetClient: Default + 'static {
+    fn forget_client(&mut self, client: Xid);
+}
+
+impl ForgetClient for RecentClients {
+    fn forget_client(&mut self, client: Xid) {
+        self.recent_clients.retain(|c| *c != client);
+        self.chronological_clients.retain(|c| *c != client);
+    }
+}
+
+impl ForgetClient for FloatingClients {
+    fn forget_client(&mut self, client: Xid) {
+        self.clients.remove(&client);
+    }
+}
+
+impl ForgetClient for ManualPlacements {
+    fn forget_client(&mut self, client: Xid) {
+        self.clients.remove(&client);
+    }
+}
+
+impl ForgetClient for BorderlessClients {
+    fn forget_client(&mut self, client: Xid) {
+        self.clients.remove(&client);
+    }
+}
+
+fn forget<E: ForgetClient, X: XConn + 'static>(state: &mut State<X>, client: Xid) {
+    state
+        .extension_or_default::<E>()
+        .borrow_mut()
+        .forget_client(client);
+}
+
+/// Event hook dropping destroyed clients from our extensions as soon as they
+/// go, so alt-tab never offers a dead client in the meantime. Everything is
+/// still pruned against the client set on refresh in case we miss one.
+fn forget_destroyed_client<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    _: &X,
+) -> Result<bool> {
+    let client = match event {
+        XEvent::Destroy(client) => *client,
+        _ => return Ok(true),
+    };
+    debug!(?client, "Forgetting destroyed client");
+    forget::<RecentClients, X>(state, client);
+    forget::<FloatingClients, X>(state, client);
+    forget::<ManualPlacements, X>(state, client);
+    forget::<BorderlessClients, X>(state, client);
+    forget::<BorderWidths, X>(state, client);
+    forget::<scratchpad::Scratchpad, X>(state, client);
+    forget::<sticky::StickyClients, X>(state, client);
+    forget::<urgent::UrgentClients, X>(state, client);
+    forget::<fullscreen::FullscreenClients, X>(state, client);
+    forget::<input::InputModels, X>(state, client);
+    forget::<opacity::Opacity, X>(state, client);
+    forget::<minimize::Minimized, X>(state, client);
+    forget::<stash::Stash, X>(state, client);
+    forget::<transients::Transients, X>(state, client);
+    forget::<MainAppClients, X>(state, client);
+
+    Ok(true)
+}
+
+fn populate_windows<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let scratchpad = scratchpad_client(state);
+    // Stashed clients stay out of the MRU lists until they're brought back
+    let stashed = stash::stashed_clients(state)
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let floating = state.extension_or_default::<FloatingClients>();
+    let mut floating = floating.borrow_mut();
+    floating
+        .clients
+        .retain(|client| state.client_set.contains(client));
+    let all_clients = state
+        .client_set
+        .clients()
+        .filter(|client| {
+            Some(**client) != scratchpad
+                && !floating.clients.contains(client)
+                && !stashed.contains(client)
+        })
+        .cloned()
+        .collect::<HashSet<_>>();
+    std::mem::drop(floating);
+    state
+        .extension_or_default::<ManualPlacements>()
+        .borrow_mut()
+        .clients
+        .retain(|client| state.client_set.contains(client));
+    let recent_clients = state.extension_or_default::<RecentClients>();
+    let mut recent_clients = recent_clients.borrow_mut();
+    recent_clients.retain_clients(&all_clients);
     let known_clients = recent_clients
         .recent_clients
         .iter()
@@ -454,89 +2527,580 @@ fn populate_windows<X: XConn + 'static>(state: &mut State<X>, _x: &X) -> Result<
             .append(&mut unknown_clients);
     }
 
+    if recent_clients.expire_switching(Instant::now(), SWITCHING_TIMEOUT) {
+        debug!("Alt was never released. Dropping task switching status!");
+    }
+    let restoring = persist::restore_recent_clients(state, x, &mut recent_clients);
+
     // Only commit changes if we're not switching tasks right now
-    if !recent_clients.switching {
+    if !recent_clients.switching && !restoring {
         if let Some(current_client) = state.client_set.current_client() {
-            if let Some(index) = recent_clients
-                .recent_clients
-                .iter()
-                .position(|client| current_client == client)
-            {
-                recent_clients.recent_clients.remove(index);
+            if recent_clients.committed_focus != Some(*current_client) {
+                if let Some(index) = recent_clients
+                    .recent_clients
+                    .iter()
+                    .position(|client| current_client == client)
+                {
+                    recent_clients.recent_clients.remove(index);
+                }
+                recent_clients.recent_clients.insert(0, *current_client);
+                recent_clients.committed_focus = Some(*current_client);
+            }
+        }
+    }
+    log_focus_history(&recent_clients, x);
+
+    Ok(())
+}
+
+/// Target for the (opt-in) focus history log.
+const FOCUS_LOG_TARGET: &str = "focus";
+
+/// Keycodes for Tab, grave and backslash on a standard PC keyboard, for when
+/// xmodmap doesn't tell us.
+const FALLBACK_TAB_CODE: u8 = 23;
+const FALLBACK_GRAVE_CODE: u8 = 49;
+const FALLBACK_BACKSLASH_CODE: u8 = 51;
+
+/// The keycodes `alt_tab_listener` switches on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct AltTabKeys {
+    tab: u8,
+    grave: u8,
+    backslash: u8,
+}
+
+impl AltTabKeys {
+    fn from_keycodes(keycodes: &HashMap<String, u8>) -> Self {
+        let lookup = |name: &str, fallback: u8| match keycodes.get(name) {
+            Some(code) => *code,
+            None => {
+                warn!("xmodmap has no keycode for {name}, assuming {fallback}");
+                fallback
+            }
+        };
+        Self {
+            tab: lookup("Tab", FALLBACK_TAB_CODE),
+            grave: lookup("grave", FALLBACK_GRAVE_CODE),
+            backslash: lookup("backslash", FALLBACK_BACKSLASH_CODE),
+        }
+    }
+}
+
+fn load_alt_tab_keys() -> Option<AltTabKeys> {
+    match keycodes_from_xmodmap() {
+        Ok(keycodes) => Some(AltTabKeys::from_keycodes(&keycodes)),
+        Err(e) => {
+            warn!("Unable to read keycodes from xmodmap, alt-tab is disabled: {e}");
+            None
+        }
+    }
+}
+
+/// Keycodes we match on ourselves rather than through the key bindings. These
+/// go stale when the keyboard layout changes, so they're re-read then.
+#[derive(Debug)]
+struct Keycodes {
+    alt_tab: Option<AltTabKeys>,
+    /// What's held down for each kind of switching. This is fixed at startup
+    /// along with the key bindings.
+    switching: SwitchConfig,
+    /// The modifier held for the switch in progress, which ends it once
+    /// released
+    switching_with: SwitchModifier,
+}
+
+impl Default for Keycodes {
+    fn default() -> Self {
+        Self {
+            alt_tab: None,
+            switching: SwitchConfig::default(),
+            switching_with: SwitchModifier::Alt,
+        }
+    }
+}
+
+/// Startup hook reading in the keycodes.
+fn load_keycodes<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    let switching = get_switch_config();
+    state.add_extension(Keycodes {
+        alt_tab: load_alt_tab_keys(),
+        switching_with: switching.modifier,
+        switching,
+    });
+    Ok(())
+}
+
+/// Event hook re-reading the keycodes when the keyboard mapping changes. XKB
+/// map changes also reach us as a core `MappingNotify`, which is the one
+/// penrose hands on.
+fn reload_keycodes<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    _: &X,
+) -> Result<bool> {
+    if !matches!(event, XEvent::MappingNotify) {
+        return Ok(true);
+    }
+    match keycodes_from_xmodmap() {
+        Ok(keycodes) => {
+            let keys = AltTabKeys::from_keycodes(&keycodes);
+            info!(?keys, "Keyboard mapping changed");
+            state
+                .extension_or_default::<Keycodes>()
+                .borrow_mut()
+                .alt_tab = Some(keys);
+        }
+        Err(e) => warn!("Unable to re-read keycodes from xmodmap, keeping the old ones: {e}"),
+    }
+
+    Ok(true)
+}
+
+/// Which way a task switching key press goes: forwards with just `modifier`
+/// held, or backwards with Shift too.
+fn switch_direction(mask: KeyCodeMask, modifier: SwitchModifier) -> Option<Direction> {
+    let modifier = KeyCodeMask::from(modifier.key());
+    match mask {
+        mask if mask == modifier => Some(Direction::Forward),
+        mask if mask == (KeyCodeMask::from(ModifierKey::Shift) | modifier) => {
+            Some(Direction::Backward)
+        }
+        _ => None,
+    }
+}
+
+fn alt_tab_listener(event: &XEvent, state: &mut State<RustConn>, x: &RustConn) -> Result<bool> {
+    // `populate_windows` may have given up on switching without us
+    if !state
+        .extension_or_default::<RecentClients>()
+        .borrow()
+        .switching
+    {
+        state
+            .extension_or_default::<AltTabOverlay>()
+            .borrow_mut()
+            .hide(x)?;
+    }
+
+    let (alt_tab, switching, switching_with) = {
+        let keycodes = state.extension_or_default::<Keycodes>();
+        let keycodes = keycodes.borrow();
+        (
+            keycodes.alt_tab,
+            keycodes.switching.clone(),
+            keycodes.switching_with,
+        )
+    };
+    let keys = match alt_tab {
+        Some(keys) => keys,
+        None => return Ok(true),
+    };
+    // debug!("Code: {event:?}");
+    let code = match event {
+        XEvent::KeyPress(code) => code,
+        XEvent::KeyRelease(code) if !code.contains(switching_with.mod_mask()) => {
+            // The modifier is no longer pressed!
+            let recent_clients = state.extension_or_default::<RecentClients>();
+            let mut recent_clients = recent_clients.borrow_mut();
+            if recent_clients.switching {
+                debug!("Alt released. Dropping task switching status!");
+                recent_clients.switching = false;
+                std::mem::drop(recent_clients);
+                state
+                    .extension_or_default::<AltTabOverlay>()
+                    .borrow_mut()
+                    .hide(x)?;
+                populate_windows(state, x)?;
+            }
+            return Ok(true);
+        }
+        _ => return Ok(true),
+    };
+    debug!("Alt tabbing... {code:?}! :)");
+
+    let context = match code.code {
+        code if code == keys.tab => SwitchContext::Global,
+        code if code == keys.grave => SwitchContext::Workspace,
+        code if code == keys.backslash => SwitchContext::SameApp,
+        _ => return Ok(true),
+    };
+    let modifier = switching.modifier_for(&context);
+    let direction = match switch_direction(code.mask, modifier) {
+        Some(direction) => direction,
+        None => return Ok(true),
+    };
+    state
+        .extension_or_default::<Keycodes>()
+        .borrow_mut()
+        .switching_with = modifier;
+
+    debug!("Alt tabbing! We have {code:?} pressed!! :)");
+
+    let recent_clients = state.extension_or_default::<RecentClients>();
+    recent_clients.borrow_mut().start_switching(Instant::now());
+    let candidates = task_switch(state, x, context, direction)?;
+
+    let focus = state.client_set.current_client().cloned();
+    let rows = candidates
+        .iter()
+        .map(|client| (get_window_title(*client, x), Some(*client) == focus))
+        .collect::<Vec<_>>();
+    let screen = state.client_set.current_screen().geometry();
+    state
+        .extension_or_default::<AltTabOverlay>()
+        .borrow_mut()
+        .show(x, screen, &rows)?;
+
+    Ok(true)
+}
+
+/// Pop up a desktop notification, for things worth more than a log line.
+fn notify(summary: &str, body: &str) {
+    if let Err(e) = util::spawn_with_args("notify-send", &["--app-name=wendy", summary, body]) {
+        warn!("Unable to send notification {summary:?}: {e}");
+    }
+}
+
+/// When `M-A-Escape` was first pressed, if it's waiting for a second press to
+/// confirm exiting.
+#[derive(Debug, Default)]
+struct PendingExit {
+    armed_at: Option<Instant>,
+}
+
+/// How long a first press of `M-A-Escape` waits for the second.
+const EXIT_CONFIRM_TIMEOUT: Duration = Duration::from_secs(3);
+
+impl PendingExit {
+    /// Record a press at `now`, returning whether it confirms one shortly
+    /// before it. Otherwise this arms us for the next one.
+    fn press(&mut self, now: Instant) -> bool {
+        match self.armed_at.take() {
+            Some(armed_at) if now.duration_since(armed_at) <= EXIT_CONFIRM_TIMEOUT => true,
+            _ => {
+                self.armed_at = Some(now);
+                false
             }
-            recent_clients.recent_clients.insert(0, *current_client);
         }
     }
-
-    Ok(())
 }
 
-lazy_static::lazy_static! {
-    static ref KEYCODES_FROM_XMODMAP: HashMap<String, u8> = keycodes_from_xmodmap().unwrap();
+/// Key handler exiting, once it's been pressed twice unless confirmation is
+/// turned off.
+fn confirm_exit<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let path = config::config_path();
+    let confirm = config::load_exit_config(&path)
+        .map(|exit| exit.confirm)
+        .unwrap_or_else(|e| {
+            warn!("Failed to load exit config from {}: {e}", path.display());
+            true
+        });
+    let confirmed = state
+        .extension_or_default::<PendingExit>()
+        .borrow_mut()
+        .press(Instant::now());
+    if confirm && !confirmed {
+        info!("Press again within {EXIT_CONFIRM_TIMEOUT:?} to exit");
+        notify(
+            "Exit?",
+            &format!("Press again within {EXIT_CONFIRM_TIMEOUT:?} to exit"),
+        );
+        return Ok(());
+    }
+
+    persist::save_recent_clients(state, x);
+    exit().call(state, x)
+}
+
+/// Key handler re-executing ourselves in place, e.g. to pick up a rebuilt
+/// binary. Unlike `M-A-Escape`, which quits and ends the session along with
+/// it, clients are left running and managed again by the new process, which
+/// sets up its key bindings and hooks from scratch. The alt-tab order and which
+/// tag each client is on are saved first so they can be picked back up.
+fn restart<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    // Put everything back where it belongs before the overview is forgotten
+    expose::cancel_expose(state, x)?;
+    persist::save_recent_clients(state, x);
+    let minimized = minimize::minimized_stack(state);
+    let stashed = stash::stashed_clients(state);
+    let swallowed = state
+        .extension_or_default::<swallow::Swallowed>()
+        .borrow()
+        .clients
+        .clone();
+    let placements = saved_placements(&state.client_set, &minimized, &stashed, &swallowed);
+    if let Err(e) = persist::save_placements(placements) {
+        warn!("Unable to save where clients are, they'll be placed from scratch: {e}");
+    }
+    // Only windows that are showing get adopted when we start back up, so
+    // everything on a tag that isn't has to be mapped for it to survive
+    for client in state.client_set.clients() {
+        x.map(*client)?;
+    }
+    info!("Restarting");
+    let e = process::Command::new("/proc/self/exe")
+        .args(env::args_os().skip(1))
+        .exec();
+    // exec only comes back if it failed
+    warn!("Unable to restart: {e}");
+    notify("Unable to restart", &e.to_string());
+
+    Ok(())
+}
+
+/// Re-read the config file and apply whatever can be changed without a
+/// restart. The lock command is read when it's used, so this only checks it
+/// still parses; everything else is swapped in, and the gaps, borders and tag
+/// layouts are brought up to date. Key bindings (and the switch modifier with
+/// them) are fixed at startup.
+fn reload_config<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let path = config::config_path();
+    let loaded = config::load_pinned_apps::<X>(&path).and_then(|apps| {
+        let main_app = config::load_main_app::<X>(&path)?;
+        let float_rules = config::load_float_rules::<X>(&path)?;
+        let overflow_config = config::load_overflow_config(&path)?;
+        let cached = CachedConfig {
+            switching: Some(config::load_switch_config(&path)?),
+            placement: Some(config::load_placement_config(&path)?),
+            swallow: Some(config::load_swallow_config(&path)?),
+        };
+        let appearance = config::load_appearance(&path)?;
+        let focused_border = Color::try_from(appearance.focused_border.as_str())?;
+        let normal_border = Color::try_from(appearance.normal_border.as_str())?;
+        config::load_tag_layouts(&path)?;
+        config::load_lock_config(&path)?;
+        Ok((
+            apps,
+            main_app,
+            float_rules,
+            overflow_config,
+            cached,
+            appearance.border_width,
+            focused_border,
+            normal_border,
+            appearance.inactive_opacity,
+        ))
+    });
+    let (
+        apps,
+        main_app,
+        float_rules,
+        overflow_config,
+        cached,
+        border_width,
+        focused_border,
+        normal_border,
+        inactive_opacity,
+    ) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            warn!("Not reloading {}: {e}", path.display());
+            notify("Unable to reload config", &e.to_string());
+            return Ok(());
+        }
+    };
+
+    state.add_extension(PinnedApps {
+        apps: Rc::new(apps),
+    });
+    state.add_extension(MainApp {
+        queries: Rc::new(main_app),
+    });
+    state.add_extension(MainAppClients::default());
+    state.add_extension(FloatRules {
+        rules: Rc::new(float_rules),
+    });
+    overflow::set_overflow_config(state, overflow_config);
+    state.add_extension(cached);
+    state.config.border_width = border_width;
+    state.config.focused_border = focused_border;
+    state.config.normal_border = normal_border;
+    opacity::set_inactive_opacity(state, inactive_opacity);
+    // Swap in the new gaps, keeping each workspace on the layout it's using
+    let tags = state
+        .client_set
+        .workspaces()
+        .map(|ws| ws.tag().to_string())
+        .collect::<Vec<_>>();
+    for tag in tags {
+        let layouts = workspace_layouts(state, &tag);
+        if let Some(workspace) = state.client_set.workspace_mut(&tag) {
+            let layout = workspace.layout_name();
+            workspace.set_available_layouts(layouts);
+            workspace.set_layout_by_name(&layout);
+        }
+    }
+    info!(
+        "Reloaded {}: apps, switching, placement, swallowing, borders, gaps and layouts",
+        path.display()
+    );
+
+    x.refresh(state)
+}
+
+/// The switching, placement and swallowing settings, each read in when it's
+/// first needed and kept until the config is reloaded.
+#[derive(Debug, Default)]
+struct CachedConfig {
+    switching: Option<SwitchConfig>,
+    placement: Option<PlacementConfig>,
+    swallow: Option<config::SwallowConfig>,
+}
+
+fn switch_config<X: XConn + 'static>(state: &mut State<X>) -> SwitchConfig {
+    let cached = state.extension_or_default::<CachedConfig>();
+    let mut cached = cached.borrow_mut();
+    cached
+        .switching
+        .get_or_insert_with(get_switch_config)
+        .clone()
+}
+
+fn placement_config<X: XConn + 'static>(state: &mut State<X>) -> PlacementConfig {
+    let cached = state.extension_or_default::<CachedConfig>();
+    let mut cached = cached.borrow_mut();
+    cached
+        .placement
+        .get_or_insert_with(get_placement_config)
+        .clone()
+}
+
+fn swallow_config<X: XConn + 'static>(state: &mut State<X>) -> config::SwallowConfig {
+    let cached = state.extension_or_default::<CachedConfig>();
+    let mut cached = cached.borrow_mut();
+    cached
+        .swallow
+        .get_or_insert_with(get_swallow_config)
+        .clone()
+}
+
+fn get_placement_config() -> PlacementConfig {
+    let path = config::config_path();
+    config::load_placement_config(&path).unwrap_or_else(|e| {
+        warn!(
+            "Failed to load placement config from {}: {e}",
+            path.display()
+        );
+        PlacementConfig::default()
+    })
+}
+
+fn get_swallow_config() -> config::SwallowConfig {
+    let path = config::config_path();
+    config::load_swallow_config(&path).unwrap_or_else(|e| {
+        warn!("Failed to load swallow config from {}: {e}", path.display());
+        config::SwallowConfig::default()
+    })
+}
+
+fn get_switch_config() -> SwitchConfig {
+    let path = config::config_path();
+    config::load_switch_config(&path).unwrap_or_else(|e| {
+        warn!(
+            "Failed to load switching config from {}: {e}",
+            path.display()
+        );
+        SwitchConfig::default()
+    })
+}
+
+/// Key handler opening a terminal in the focused tag's directory, if it has one.
+fn spawn_terminal<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    let path = config::config_path();
+    let directories = config::load_tag_directories(&path).unwrap_or_else(|e| {
+        warn!(
+            "Failed to load tag directories from {}: {e}",
+            path.display()
+        );
+        HashMap::new()
+    });
+    match directories.get(state.client_set.current_tag()) {
+        Some(dir) => {
+            let dir = dir.to_string_lossy();
+            util::spawn_with_args("alacritty", &["--working-directory", &dir])
+        }
+        None => util::spawn("alacritty"),
+    }
+}
+
+fn get_lock_config() -> LockConfig {
+    let path = config::config_path();
+    config::load_lock_config(&path).unwrap_or_else(|e| {
+        warn!("Failed to load lock config from {}: {e}", path.display());
+        LockConfig::default()
+    })
 }
 
-fn alt_tab_listener<X: XConn + 'static>(
-    event: &XEvent,
-    state: &mut State<X>,
-    x: &X,
-) -> Result<bool> {
-    let tab_code = *KEYCODES_FROM_XMODMAP.get("Tab").unwrap();
-    let backtick_code = *KEYCODES_FROM_XMODMAP.get("grave").unwrap();
-    // println!("Code: {event:?}");
-    let code = match event {
-        XEvent::KeyPress(code) => code,
-        XEvent::KeyRelease(code) if !code.contains(ModMask::M1) => {
-            // M1 is no longer pressed!
-            let recent_clients = state.extension_or_default::<RecentClients>();
-            let mut recent_clients = recent_clients.borrow_mut();
-            if recent_clients.switching {
-                println!("Alt released. Dropping task switching status!");
-                recent_clients.switching = false;
-                std::mem::drop(recent_clients);
-                populate_windows(state, x)?;
-            }
-            return Ok(true);
+fn start_lock_daemon<X: XConn + 'static>(_: &mut State<X>, _: &X) -> Result<()> {
+    match get_lock_config().daemon {
+        Some(daemon) => util::spawn(daemon),
+        None => {
+            info!("No lock daemon is configured, not starting one");
+            Ok(())
         }
-        _ => return Ok(true),
-    };
-    println!("Alt tabbing... {code:?}! :)");
+    }
+}
 
-    let context = match code.code {
-        code if code == tab_code => SwitchContext::Global,
-        code if code == backtick_code => SwitchContext::Workspace,
-        _ => return Ok(true),
-    };
-    let direction = match code.mask {
-        mask if mask == KeyCodeMask::from(ModifierKey::Alt) => Direction::Forward,
-        mask if mask
-            == (KeyCodeMask::from(ModifierKey::Shift) | KeyCodeMask::from(ModifierKey::Alt)) =>
-        {
-            Direction::Backward
+/// Whether a process called `name` is running.
+fn is_running(name: &str) -> bool {
+    process::Command::new("pgrep")
+        .args(["-x", name])
+        .stdout(Stdio::null())
+        .status()
+        .map_or(false, |status| status.success())
+}
+
+/// Startup hook launching everything under `[[autostart]]`. One entry failing
+/// to launch doesn't stop the rest.
+fn run_autostart<X: XConn + 'static>(_: &mut State<X>, _: &X) -> Result<()> {
+    let path = config::config_path();
+    let entries = match config::load_autostart(&path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to load autostart from {}: {e}", path.display());
+            return Ok(());
         }
-        _ => return Ok(true),
     };
+    for entry in entries {
+        if let Some(process) = entry.process.as_deref().filter(|p| is_running(p)) {
+            info!(
+                "{process} is already running, not launching `{}`",
+                entry.command
+            );
+            continue;
+        }
+        info!("Launching `{}`", entry.command);
+        if let Err(e) = util::spawn(entry.command.as_str()) {
+            warn!("Failed to launch `{}`: {e}", entry.command);
+        }
+    }
 
-    println!("Alt tabbing! We have {code:?} pressed!! :)");
-
-    let recent_clients = state.extension_or_default::<RecentClients>();
-    recent_clients.borrow_mut().switching = true;
-    task_switch(state, x, context, direction)?;
-
-    Ok(true)
-}
-
-fn start_xscreensaver<X: XConn + 'static>(_: &mut State<X>, _: &X) -> Result<()> {
-    util::spawn("xscreensaver")
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let _ = KEYCODES_FROM_XMODMAP.get("Tab").unwrap();
-
-    tracing_subscriber::fmt()
-        .with_env_filter("info")
-        .finish()
+    // Setting $WENDY_FOCUS_LOG to a directory logs the MRU lists there as they
+    // change, rotated daily.
+    let focus_log = env::var_os("WENDY_FOCUS_LOG").map(|dir| {
+        tracing_subscriber::fmt::layer()
+            .with_writer(tracing_appender::rolling::daily(dir, "focus.log"))
+            .with_ansi(false)
+            .with_filter(Targets::new().with_target(FOCUS_LOG_TARGET, Level::DEBUG))
+    });
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(logging::reloadable_filter()))
+        .with(focus_log)
         .init();
 
+    // Key bindings are all resolved through xmodmap, so there's no point going
+    // any further without it.
+    keycodes_from_xmodmap().map_err(|e| {
+        Error::Custom(format!(
+            "unable to read keycodes from xmodmap, is it installed? ({e})"
+        ))
+    })?;
+
     let conn = RustConn::new()?;
 
     {
@@ -573,18 +3137,71 @@ fn main() -> Result<()> {
         )?;
     }
 
-    let key_bindings = parse_keybindings_with_xmodmap(raw_key_bindings())?;
+    let key_bindings = parse_keybindings_with_xmodmap(raw_key_bindings(&get_switch_config()))?;
     let mut config = add_ewmh_hooks(Config::default());
     config.tags = TAGS.into_iter().map(String::from).collect();
     config.focus_follow_mouse = false;
-    config.default_layouts = default_layout_factory();
+    let appearance = get_appearance();
+    config.border_width = appearance.border_width;
+    config.focused_border = Color::try_from(appearance.focused_border.as_str())?;
+    config.normal_border = Color::try_from(appearance.normal_border.as_str())?;
+    config.default_layouts = layout_for_tag(TAGS[0]);
+    config.compose_or_set_manage_hook(float_dialogs);
+    config.compose_or_set_manage_hook(transients::record_transient);
     config.compose_or_set_manage_hook(move_pinned_windows);
+    config.compose_or_set_manage_hook(scratchpad::manage_scratchpad);
     config.compose_or_set_manage_hook(populate_new_window);
-    config.compose_or_set_refresh_hook(backfill_gaps);
+    config.compose_or_set_manage_hook(swallow::swallow_terminal);
+    config.compose_or_set_manage_hook(input::record_input_model);
+    config.compose_or_set_manage_hook(record_borderless);
+    config.compose_or_set_refresh_hook(struts::reserve_struts);
+    config.compose_or_set_refresh_hook(expose::restore_on_leave);
+    config.compose_or_set_refresh_hook(debounced_backfill_gaps);
+    config.compose_or_set_refresh_hook(sticky::follow_focus);
     config.compose_or_set_refresh_hook(populate_windows);
+    config.compose_or_set_refresh_hook(recover_focus);
+    config.compose_or_set_refresh_hook(track_previous_tag);
+    config.compose_or_set_refresh_hook(persist::save_recent_clients_periodically);
+    config.compose_or_set_refresh_hook(screens::remember_screen_tags);
+    config.compose_or_set_refresh_hook(keep_main_app);
+    config.compose_or_set_refresh_hook(overflow::overflow_crowded_monocle);
+    config.compose_or_set_refresh_hook(smart_borders);
+    config.compose_or_set_refresh_hook(app_border_colors);
+    config.compose_or_set_refresh_hook(opacity::dim_unfocused);
+    config.compose_or_set_refresh_hook(urgent::clear_focused_urgency);
+    config.compose_or_set_refresh_hook(input::send_take_focus);
+    config.compose_or_set_refresh_hook(ipc::notify_subscribers);
+    config.compose_or_set_refresh_hook(tag_names::publish_desktop_names);
+    config.compose_or_set_event_hook(reload_keycodes);
+    config.compose_or_set_event_hook(swallow::unswallow_listener);
+    config.compose_or_set_event_hook(forget_destroyed_client);
     config.compose_or_set_event_hook(alt_tab_listener);
-    config.compose_or_set_startup_hook(start_xscreensaver);
-    let wm = WindowManager::new(config, key_bindings, HashMap::new(), conn)?;
+    config.compose_or_set_event_hook(ipc::ipc_listener);
+    config.compose_or_set_event_hook(launcher::launcher_listener);
+    config.compose_or_set_event_hook(persist::finish_restore);
+    config.compose_or_set_event_hook(backfill_gaps_listener);
+    config.compose_or_set_event_hook(resort_listener);
+    config.compose_or_set_event_hook(fullscreen::fullscreen_listener);
+    config.compose_or_set_event_hook(urgent::urgency_listener);
+    config.compose_or_set_event_hook(activate::active_window_listener);
+    config.compose_or_set_event_hook(desktops::desktop_listener);
+    config.compose_or_set_event_hook(struts::dock_listener);
+    config.compose_or_set_event_hook(follow_mouse::follow_mouse_listener);
+    config.compose_or_set_event_hook(idle::idle_lock_listener);
+    config.compose_or_set_startup_hook(load_keycodes);
+    config.compose_or_set_startup_hook(apply_tag_layouts);
+    config.compose_or_set_startup_hook(outputs::assign_output_tags);
+    config.compose_or_set_startup_hook(struts::find_docks);
+    config.compose_or_set_startup_hook(resort_existing_clients);
+    config.compose_or_set_startup_hook(opacity::load_opacity);
+    config.compose_or_set_startup_hook(start_lock_daemon);
+    config.compose_or_set_startup_hook(idle::start_idle_lock);
+    config.compose_or_set_startup_hook(run_autostart);
+    config.compose_or_set_startup_hook(ipc::start_ipc);
+    config.compose_or_set_startup_hook(launcher::start_launcher);
+    config.compose_or_set_startup_hook(persist::load_recent_clients);
+    config.compose_or_set_startup_hook(start_backfill_debounce);
+    let wm = WindowManager::new(config, key_bindings, mouse_bindings(), conn)?;
 
     wm.run()
 }
@@ -592,13 +3209,736 @@ fn main() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use penrose::pure::{Stack, StackSet};
+
+    /// The default layouts, built without reading the user's config.
+    fn test_layouts() -> LayoutStack {
+        default_layouts(&Appearance::default(), LayoutKind::MainAndStack)
+    }
 
     #[test]
     fn bindings_parse_correctly_with_xmodmap() {
-        let res = parse_keybindings_with_xmodmap(raw_key_bindings());
+        let res = parse_keybindings_with_xmodmap(raw_key_bindings(&SwitchConfig::default()));
 
         if let Err(e) = res {
             panic!("{e}");
         }
     }
+
+    #[test]
+    fn switching_expires_after_timeout() {
+        let mut recent_clients = RecentClients::default();
+        let start = Instant::now();
+        recent_clients.start_switching(start);
+
+        let elapsed = start + Duration::from_secs(1);
+        assert!(!recent_clients.expire_switching(elapsed, SWITCHING_TIMEOUT));
+        assert!(recent_clients.switching);
+
+        assert!(recent_clients.expire_switching(
+            start + SWITCHING_TIMEOUT + Duration::from_secs(1),
+            SWITCHING_TIMEOUT
+        ));
+        assert!(!recent_clients.switching);
+    }
+
+    #[test]
+    fn task_switching_wraps_around_the_ends() {
+        assert_eq!(next_position(0, 3, &Direction::Forward, true), 1);
+        assert_eq!(next_position(2, 3, &Direction::Forward, true), 0);
+        assert_eq!(next_position(1, 3, &Direction::Backward, true), 0);
+        assert_eq!(next_position(0, 3, &Direction::Backward, true), 2);
+        assert_eq!(next_position(0, 1, &Direction::Forward, true), 0);
+    }
+
+    #[test]
+    fn task_switching_stops_at_the_ends_without_wrapping() {
+        assert_eq!(next_position(0, 3, &Direction::Forward, false), 1);
+        assert_eq!(next_position(2, 3, &Direction::Forward, false), 2);
+        assert_eq!(next_position(1, 3, &Direction::Backward, false), 0);
+        assert_eq!(next_position(0, 3, &Direction::Backward, false), 0);
+        assert_eq!(next_position(0, 1, &Direction::Backward, false), 0);
+    }
+
+    #[test]
+    fn exiting_needs_a_second_press_in_time() {
+        let mut pending = PendingExit::default();
+        let start = Instant::now();
+
+        assert!(!pending.press(start));
+        assert!(pending.press(start + Duration::from_secs(1)));
+
+        // Too slow, so that press only arms it again
+        assert!(!pending.press(start));
+        assert!(!pending.press(start + EXIT_CONFIRM_TIMEOUT + Duration::from_secs(1)));
+        assert!(pending.press(start + EXIT_CONFIRM_TIMEOUT + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn new_windows_go_to_the_front_by_default() {
+        let (a, b, c) = (Xid::from(1), Xid::from(2), Xid::from(3));
+        let mut recent_clients = RecentClients::default();
+        for client in [a, b, c] {
+            recent_clients.add(client, true);
+        }
+
+        assert_eq!(recent_clients.recent_clients, vec![c, b, a]);
+        assert_eq!(recent_clients.chronological_clients, vec![a, b, c]);
+        assert!(config::PlacementConfig::default().new_window_to_front);
+    }
+
+    #[test]
+    fn new_windows_can_go_to_the_back() {
+        let (a, b, c) = (Xid::from(1), Xid::from(2), Xid::from(3));
+        let mut recent_clients = RecentClients::default();
+        recent_clients.add(a, true);
+        for client in [b, c] {
+            recent_clients.add(client, false);
+        }
+
+        assert_eq!(recent_clients.recent_clients, vec![a, b, c]);
+        assert_eq!(recent_clients.chronological_clients, vec![a, b, c]);
+    }
+
+    #[test]
+    fn destroyed_clients_are_forgotten() {
+        let (gone, kept) = (Xid::from(1), Xid::from(2));
+        let both = HashSet::from([gone, kept]);
+
+        let mut recent_clients = RecentClients {
+            recent_clients: vec![gone, kept],
+            chronological_clients: vec![kept, gone],
+            ..Default::default()
+        };
+        let mut floating = FloatingClients {
+            clients: both.clone(),
+        };
+        let mut manual = ManualPlacements {
+            clients: both.clone(),
+        };
+        let mut borderless = BorderlessClients {
+            clients: both.clone(),
+        };
+        let mut sticky = sticky::StickyClients {
+            clients: both.clone(),
+        };
+        let mut fullscreen = fullscreen::FullscreenClients::default();
+        fullscreen.clients.extend([gone, kept]);
+        let mut urgent = urgent::UrgentClients::default();
+        urgent.clients.extend([gone, kept]);
+
+        recent_clients.forget_client(gone);
+        floating.forget_client(gone);
+        manual.forget_client(gone);
+        borderless.forget_client(gone);
+        sticky.forget_client(gone);
+        fullscreen.forget_client(gone);
+        urgent.forget_client(gone);
+
+        assert_eq!(recent_clients.recent_clients, vec![kept]);
+        assert_eq!(recent_clients.chronological_clients, vec![kept]);
+        let only_kept = HashSet::from([kept]);
+        assert_eq!(floating.clients, only_kept);
+        assert_eq!(manual.clients, only_kept);
+        assert_eq!(borderless.clients, only_kept);
+        assert_eq!(sticky.clients, only_kept);
+        assert_eq!(fullscreen.clients, only_kept);
+        assert_eq!(urgent.clients, only_kept);
+    }
+
+    #[test]
+    fn shifting_clients_stops_at_the_ends() {
+        let (a, b, c) = (Xid::from(1), Xid::from(2), Xid::from(3));
+        let mut recent_clients = RecentClients {
+            recent_clients: vec![a, b, c],
+            ..Default::default()
+        };
+
+        assert!(!recent_clients.shift(a, Direction::Backward));
+        assert!(!recent_clients.shift(c, Direction::Forward));
+        assert!(!recent_clients.shift(Xid::from(4), Direction::Forward));
+        assert_eq!(recent_clients.recent_clients, vec![a, b, c]);
+
+        assert!(recent_clients.shift(c, Direction::Backward));
+        assert_eq!(recent_clients.recent_clients, vec![a, c, b]);
+        assert!(recent_clients.shift(a, Direction::Forward));
+        assert_eq!(recent_clients.recent_clients, vec![c, a, b]);
+    }
+
+    #[test]
+    fn selecting_from_no_clients_finds_nothing() {
+        assert_eq!(
+            select_next_client(&[], None, Direction::Forward, true),
+            None
+        );
+        assert_eq!(
+            select_next_client(&[], Some(Xid::from(1)), Direction::Backward, false),
+            None
+        );
+    }
+
+    #[test]
+    fn selecting_from_a_single_client_stays_put() {
+        let a = Xid::from(1);
+        for wrap in [true, false] {
+            assert_eq!(
+                select_next_client(&[a], Some(a), Direction::Forward, wrap),
+                Some(a)
+            );
+            assert_eq!(
+                select_next_client(&[a], Some(a), Direction::Backward, wrap),
+                Some(a)
+            );
+        }
+    }
+
+    #[test]
+    fn selecting_steps_forward_and_backward() {
+        let clients = [Xid::from(1), Xid::from(2), Xid::from(3)];
+        let select = |focused, direction| select_next_client(&clients, focused, direction, false);
+
+        assert_eq!(
+            select(Some(clients[1]), Direction::Forward),
+            Some(clients[2])
+        );
+        assert_eq!(
+            select(Some(clients[1]), Direction::Backward),
+            Some(clients[0])
+        );
+        // Nothing focused, or something not in the list, counts as the start
+        assert_eq!(select(None, Direction::Forward), Some(clients[1]));
+        assert_eq!(
+            select(Some(Xid::from(4)), Direction::Forward),
+            Some(clients[1])
+        );
+    }
+
+    #[test]
+    fn selecting_wraps_only_when_asked() {
+        let clients = [Xid::from(1), Xid::from(2), Xid::from(3)];
+        let select =
+            |focused, direction, wrap| select_next_client(&clients, focused, direction, wrap);
+
+        assert_eq!(
+            select(Some(clients[2]), Direction::Forward, true),
+            Some(clients[0])
+        );
+        assert_eq!(
+            select(Some(clients[0]), Direction::Backward, true),
+            Some(clients[2])
+        );
+        assert_eq!(
+            select(Some(clients[2]), Direction::Forward, false),
+            Some(clients[2])
+        );
+        assert_eq!(
+            select(Some(clients[0]), Direction::Backward, false),
+            Some(clients[0])
+        );
+    }
+
+    #[test]
+    fn screen_cycling_skips_empty_screens_and_wraps() {
+        let (left, right) = (Xid::from(1), Xid::from(2));
+        let mut client_set = StackSet::try_new(
+            test_layouts(),
+            TAGS,
+            vec![
+                Rect::new(0, 0, 1920, 1080),
+                Rect::new(1920, 0, 1920, 1080),
+                Rect::new(3840, 0, 1920, 1080),
+            ],
+        )
+        .unwrap();
+        client_set.focus_tag("3");
+        client_set.insert(right);
+        client_set.focus_tag("1");
+
+        assert_eq!(
+            next_screen_client(&client_set, &Direction::Forward),
+            Some((2, right))
+        );
+        assert_eq!(
+            next_screen_client(&client_set, &Direction::Backward),
+            Some((2, right))
+        );
+
+        client_set.insert(left);
+        client_set.focus_tag("3");
+        assert_eq!(
+            next_screen_client(&client_set, &Direction::Forward),
+            Some((0, left))
+        );
+        assert_eq!(
+            next_screen_client(&test_client_set(), &Direction::Forward),
+            None
+        );
+    }
+
+    #[test]
+    fn switch_scopes_can_have_their_own_modifiers() {
+        let mut switching = SwitchConfig {
+            modifier: SwitchModifier::Super,
+            ..Default::default()
+        };
+        for context in [
+            SwitchContext::Global,
+            SwitchContext::Workspace,
+            SwitchContext::SameApp,
+        ] {
+            assert_eq!(switching.modifier_for(&context), SwitchModifier::Super);
+        }
+
+        switching.workspace_modifier = Some(SwitchModifier::Alt);
+        assert_eq!(
+            switching.modifier_for(&SwitchContext::Global),
+            SwitchModifier::Super
+        );
+        assert_eq!(
+            switching.modifier_for(&SwitchContext::Workspace),
+            SwitchModifier::Alt
+        );
+        assert_eq!(
+            switching.modifier_for(&SwitchContext::SameApp),
+            SwitchModifier::Super
+        );
+    }
+
+    #[test]
+    fn switch_direction_follows_the_configured_modifier() {
+        let modifier = SwitchModifier::Super;
+        let meta = KeyCodeMask::from(ModifierKey::Meta);
+        let shift = KeyCodeMask::from(ModifierKey::Shift);
+        let alt = KeyCodeMask::from(ModifierKey::Alt);
+
+        assert_eq!(switch_direction(meta, modifier), Some(Direction::Forward));
+        assert_eq!(
+            switch_direction(meta | shift, modifier),
+            Some(Direction::Backward)
+        );
+        assert_eq!(switch_direction(alt, modifier), None);
+        assert_eq!(switch_direction(alt | shift, modifier), None);
+    }
+
+    #[test]
+    fn remapped_clients_are_not_duplicated() {
+        let (a, b, c) = (Xid::from(1), Xid::from(2), Xid::from(3));
+        let mut recent_clients = RecentClients::default();
+        recent_clients.add(a, true);
+        recent_clients.add(b, true);
+        recent_clients.add(c, true);
+        recent_clients.retain_clients(&HashSet::from([a, c]));
+        recent_clients.add(b, true);
+        recent_clients.add(a, true);
+
+        for clients in [
+            &recent_clients.recent_clients,
+            &recent_clients.chronological_clients,
+        ] {
+            let unique = clients.iter().collect::<HashSet<_>>();
+            assert_eq!(unique.len(), clients.len(), "{clients:?}");
+        }
+        assert_eq!(recent_clients.recent_clients, vec![a, b, c]);
+        assert_eq!(recent_clients.chronological_clients, vec![c, b, a]);
+    }
+
+    #[test]
+    fn duplicate_pinned_apps_are_flagged() {
+        let conflicts = config::pinned_app_conflicts([
+            (vec!["1"], "emacs"),
+            (vec!["3"], "chromium"),
+            (vec!["3"], "firefox"),
+            (vec!["5"], "slack"),
+            (vec!["6"], "slack"),
+        ]);
+
+        assert_eq!(conflicts.len(), 2, "{conflicts:?}");
+        assert!(conflicts[0].contains("\"3\""), "{conflicts:?}");
+        assert!(conflicts[1].contains("slack"), "{conflicts:?}");
+    }
+
+    #[test]
+    fn apps_spanning_tags_only_conflict_on_their_tags() {
+        let conflicts =
+            config::pinned_app_conflicts([(vec!["3", "6"], "chromium"), (vec!["1"], "emacs")]);
+        assert!(conflicts.is_empty(), "{conflicts:?}");
+
+        let conflicts =
+            config::pinned_app_conflicts([(vec!["3", "6"], "chromium"), (vec!["6"], "slack")]);
+        assert_eq!(conflicts.len(), 1, "{conflicts:?}");
+        assert!(conflicts[0].contains("\"6\""), "{conflicts:?}");
+    }
+
+    #[test]
+    fn default_pinned_apps_do_not_conflict() {
+        let apps = default_pinned_apps::<RustConn>();
+        let conflicts = config::pinned_app_conflicts(
+            apps.iter()
+                .map(|(tag, app)| (vec![tag.as_str()], app.command.as_str())),
+        );
+        assert!(conflicts.is_empty(), "{conflicts:?}");
+    }
+
+    #[test]
+    fn pinned_apps_are_not_respawned_while_starting_up() {
+        let mut pending = PendingSpawns::default();
+        let start = Instant::now();
+        assert!(!pending.recently_spawned("slack", start));
+
+        pending.commands.insert("slack".to_string(), start);
+        assert!(pending.recently_spawned("slack", start + Duration::from_secs(1)));
+        assert!(!pending.recently_spawned("emacs", start + Duration::from_secs(1)));
+        assert!(!pending.recently_spawned("slack", start + SPAWN_GUARD));
+    }
+
+    #[test]
+    fn workspace_with_only_scratchpad_is_effectively_empty() {
+        let scratchpad = Xid::from(1);
+        let ws = Workspace::new(0, "6", test_layouts(), Some(Stack::new([], scratchpad, [])));
+
+        assert!(is_effectively_empty(&ws, &HashSet::from([scratchpad])));
+        assert!(!is_effectively_empty(&ws, &HashSet::new()));
+    }
+
+    #[test]
+    fn workspace_with_other_clients_is_not_effectively_empty() {
+        let scratchpad = Xid::from(1);
+        let ws = Workspace::new(
+            0,
+            "6",
+            test_layouts(),
+            Some(Stack::new([Xid::from(2)], scratchpad, [])),
+        );
+
+        assert!(!is_effectively_empty(&ws, &HashSet::from([scratchpad])));
+    }
+
+    #[test]
+    fn internal_windows_are_skipped_by_placement() {
+        let (internal, app) = (Xid::from(1), Xid::from(2));
+        let client_set = client_set_with(&[(internal, "3"), (app, "3")], "3");
+        let kinds = HashMap::from([
+            (
+                internal,
+                ClientKind {
+                    internal: true,
+                    ..Default::default()
+                },
+            ),
+            (app, ClientKind::default()),
+        ]);
+        // Both look like they belong on tag 1, but only the app gets moved
+        let clients = resort_candidates(&client_set, &kinds, &HashSet::new())
+            .into_iter()
+            .map(|client| {
+                let facts = PlacementFacts {
+                    pinned_tag: Some("1".to_string()),
+                    pinned_tags: HashSet::from(["1".to_string()]),
+                    ..Default::default()
+                };
+                (client, facts)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            pinned_relocations(&client_set, &clients),
+            vec![(app, "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn turning_gaps_off_follows_moved_windows() {
+        let mut gapless = Gapless::default();
+        gapless.tags.insert("4".to_string());
+        gapless.tags.insert("2".to_string());
+
+        gapless.follow("4", "2");
+        assert!(!gapless.has_gaps("2"));
+        assert!(gapless.has_gaps("4"));
+
+        // Windows with gaps don't take them away from where they land
+        gapless.tags.insert("3".to_string());
+        gapless.follow("1", "3");
+        assert!(gapless.has_gaps("3"));
+    }
+
+    #[test]
+    fn tag_ten_lives_on_the_zero_key() {
+        assert_eq!(key_for_tag("3"), "3");
+        assert_eq!(key_for_tag("10"), "0");
+    }
+
+    fn test_client_set() -> ClientSet {
+        StackSet::try_new(test_layouts(), TAGS, vec![Rect::new(0, 0, 1920, 1080)]).unwrap()
+    }
+
+    /// A client set with `clients` on each of their tags, and `tag` focused.
+    fn client_set_with(clients: &[(Xid, &str)], tag: &str) -> ClientSet {
+        let mut client_set = test_client_set();
+        for (client, tag) in clients {
+            client_set.focus_tag(tag);
+            client_set.insert(*client);
+        }
+        client_set.focus_tag(tag);
+        client_set
+    }
+
+    #[test]
+    fn pinned_clients_go_to_their_tag() {
+        let client = Xid::from(1);
+        let client_set = client_set_with(&[(client, "1")], "1");
+        let facts = PlacementFacts {
+            pinned_tag: Some("4".to_string()),
+            pinned_tags: HashSet::from(["4".to_string()]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            choose_tag(client, &client_set, &facts),
+            ("4".to_string(), PlacementReason::Pinned)
+        );
+    }
+
+    #[test]
+    fn full_pinned_tags_overflow() {
+        let (client, existing) = (Xid::from(1), Xid::from(2));
+        let client_set = client_set_with(&[(existing, "4"), (client, "1")], "1");
+        let facts = PlacementFacts {
+            pinned_tag: Some("4".to_string()),
+            pinned_tags: HashSet::from(["4".to_string()]),
+            max_clients: Some(1),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            choose_tag(client, &client_set, &facts),
+            ("2".to_string(), PlacementReason::EmptyWorkspace)
+        );
+    }
+
+    #[test]
+    fn clients_join_the_rest_of_their_app() {
+        let (client, sibling) = (Xid::from(1), Xid::from(2));
+        let client_set = client_set_with(&[(sibling, "3"), (client, "1")], "1");
+        let facts = PlacementFacts {
+            group: HashSet::from([sibling]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            choose_tag(client, &client_set, &facts),
+            ("3".to_string(), PlacementReason::GroupedWithExisting)
+        );
+    }
+
+    #[test]
+    fn clients_go_to_the_first_free_unpinned_tag() {
+        let (client, other, floating) = (Xid::from(1), Xid::from(2), Xid::from(3));
+        let client_set = client_set_with(&[(other, "1"), (floating, "3"), (client, "1")], "1");
+        let facts = PlacementFacts {
+            pinned_tags: HashSet::from(["2".to_string()]),
+            ignored: HashSet::from([floating]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            choose_tag(client, &client_set, &facts),
+            ("3".to_string(), PlacementReason::EmptyWorkspace)
+        );
+    }
+
+    #[test]
+    fn new_tags_are_made_once_everything_is_taken() {
+        let client = Xid::from(100);
+        let mut clients = (1..=10)
+            .zip(TAGS)
+            .map(|(id, tag)| (Xid::from(id), tag))
+            .collect::<Vec<_>>();
+        clients.push((client, "1"));
+        let client_set = client_set_with(&clients, "1");
+
+        assert_eq!(
+            choose_tag(client, &client_set, &PlacementFacts::default()),
+            ("11".to_string(), PlacementReason::NewTag)
+        );
+    }
+
+    #[test]
+    fn crowded_monocle_overflows_until_changed_by_hand() {
+        let layouts = ("Mono", "Side");
+        let mut overflow = overflow::Overflow::default();
+        let mut step = |layout: &str, count: usize| {
+            let next = overflow.update("1", layout, count, 4, layouts);
+            overflow.remember("1", next.clone().unwrap_or(layout.to_string()));
+            next
+        };
+
+        assert_eq!(step("Mono", 4), None);
+        assert_eq!(step("Mono", 5), Some("Side".to_string()));
+        assert_eq!(step("Side", 6), None);
+        assert_eq!(step("Side", 4), Some("Mono".to_string()));
+
+        // Picking a layout by hand sticks until the workspace is emptied
+        assert_eq!(step("Side", 2), None);
+        assert_eq!(step("Mono", 5), None);
+        assert_eq!(step("Mono", 0), None);
+        assert_eq!(step("Mono", 5), Some("Side".to_string()));
+    }
+
+    #[test]
+    fn drifted_pinned_clients_are_sent_home() {
+        let (drifted, settled, unpinned) = (Xid::from(1), Xid::from(2), Xid::from(3));
+        let client_set = client_set_with(&[(drifted, "6"), (settled, "4"), (unpinned, "7")], "6");
+        let pinned_to = |tag: &str| PlacementFacts {
+            pinned_tag: Some(tag.to_string()),
+            pinned_tags: HashSet::from(["1".to_string(), "4".to_string()]),
+            ..Default::default()
+        };
+        let clients = vec![
+            (drifted, pinned_to("1")),
+            (settled, pinned_to("4")),
+            (unpinned, PlacementFacts::default()),
+        ];
+
+        assert_eq!(
+            pinned_relocations(&client_set, &clients),
+            vec![(drifted, "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn minimized_clients_leave_their_tag_free() {
+        let (client, minimized) = (Xid::from(1), Xid::from(2));
+        let mut client_set = client_set_with(&[(minimized, "1")], "1");
+        client_set
+            .add_workspace(minimize::MINIMIZED_TAG, test_layouts())
+            .unwrap();
+        client_set.move_client_to_tag(&minimized, minimize::MINIMIZED_TAG);
+        client_set.focus_tag("2");
+        client_set.insert(client);
+
+        assert_eq!(
+            choose_tag(client, &client_set, &PlacementFacts::default()),
+            ("1".to_string(), PlacementReason::EmptyWorkspace)
+        );
+        assert!(
+            backfill_moves(&client_set, |ws| is_hidden_tag(ws.tag()), &HashSet::new())
+                .iter()
+                .all(|(old_tag, new_tag)| old_tag != minimize::MINIMIZED_TAG
+                    && new_tag != minimize::MINIMIZED_TAG)
+        );
+    }
+
+    #[test]
+    fn placements_are_saved_for_hidden_tags_too() {
+        let (shown, terminal, mpv, minimized) =
+            (Xid::from(1), Xid::from(2), Xid::from(3), Xid::from(4));
+        let mut client_set = client_set_with(&[(shown, "1"), (mpv, "3"), (terminal, "3")], "1");
+        for tag in [swallow::SWALLOW_TAG, minimize::MINIMIZED_TAG] {
+            client_set.add_workspace(tag, test_layouts()).unwrap();
+        }
+        client_set.move_client_to_tag(&terminal, swallow::SWALLOW_TAG);
+        client_set.insert(minimized);
+        client_set.move_client_to_tag(&minimized, minimize::MINIMIZED_TAG);
+        let swallowed = HashMap::from([(mpv, (terminal, "3".to_string()))]);
+        let placements = saved_placements(
+            &client_set,
+            &[(minimized, "1".to_string())],
+            &[],
+            &swallowed,
+        );
+
+        let placement = |client: Xid| placements.iter().find(|p| p.id == *client).cloned();
+        assert_eq!(placements.len(), 4);
+        assert_eq!(placement(mpv).map(|p| p.tag), Some("3".to_string()));
+        assert_eq!(
+            placement(terminal),
+            Some(SavedPlacement {
+                id: *terminal,
+                tag: swallow::SWALLOW_TAG.to_string(),
+                from: Some("3".to_string()),
+                swallowed_by: Some(*mpv),
+            })
+        );
+        assert_eq!(
+            placement(minimized).and_then(|p| p.from),
+            Some("1".to_string())
+        );
+    }
+
+    #[test]
+    fn backfilling_moves_workspaces_down_into_gaps() {
+        let (a, b, pinned) = (Xid::from(1), Xid::from(2), Xid::from(3));
+        let client_set = client_set_with(&[(pinned, "1"), (a, "3"), (b, "5")], "3");
+        let fixed = |ws: &Workspace<Xid>| ws.tag() == "1";
+
+        assert_eq!(
+            backfill_moves(&client_set, fixed, &HashSet::new()),
+            vec![
+                ("3".to_string(), "2".to_string()),
+                ("5".to_string(), "3".to_string())
+            ]
+        );
+        let compact = client_set_with(&[(pinned, "1"), (a, "2")], "2");
+        assert!(backfill_moves(&compact, fixed, &HashSet::new()).is_empty());
+    }
+
+    #[test]
+    fn workspace_switching_follows_compacted_clients() {
+        let (a, b, other) = (Xid::from(1), Xid::from(2), Xid::from(3));
+        let mut client_set = test_client_set();
+        client_set.focus_tag("1");
+        client_set.insert(other);
+        client_set.focus_tag("7");
+        client_set.insert(a);
+        client_set.insert(b);
+        let recent_clients = vec![b, other, a];
+
+        // What backfill_gaps does when tag 6 is free
+        for client in [a, b] {
+            client_set.move_client_to_tag(&client, "6");
+        }
+        client_set.focus_tag("6");
+
+        assert_eq!(
+            switch_candidates(&client_set, &recent_clients, &SwitchContext::Workspace),
+            vec![b, a]
+        );
+        assert_eq!(
+            switch_candidates(&client_set, &recent_clients, &SwitchContext::Global),
+            recent_clients
+        );
+    }
+
+    #[test]
+    fn closing_the_last_window_on_a_tag_recovers_focus() {
+        let (a, b) = (Xid::from(1), Xid::from(2));
+        let mut client_set = test_client_set();
+        client_set.focus_tag("1");
+        client_set.insert(a);
+        client_set.focus_tag("3");
+        client_set.insert(b);
+
+        client_set.remove_client(&b);
+
+        assert_eq!(client_set.current_client(), None);
+        assert_eq!(recovery_target(&client_set, &[a], true), Some(a));
+        // Going to an empty tag on purpose stays put
+        assert_eq!(recovery_target(&client_set, &[a], false), None);
+    }
+
+    #[test]
+    fn focused_clients_are_left_alone() {
+        let (a, b) = (Xid::from(1), Xid::from(2));
+        let mut client_set = test_client_set();
+        client_set.insert(a);
+        client_set.insert(b);
+
+        assert_eq!(recovery_target(&client_set, &[a, b], true), None);
+    }
+
+    #[test]
+    fn default_layouts_can_be_cycled() {
+        assert!(test_layouts().len() > 1);
+    }
 }
@@ -0,0 +1,113 @@
+//! Minimizing clients out of the way onto a hidden tag.
+//!
+//! `M-n` tucks the focused client away and `M-S-n` brings back whichever was
+//! minimized most recently, on the tag it came from. Minimized clients are
+//! skipped when alt-tabbing and don't keep a workspace occupied.
+use crate::{
+    create_tag, default_layout_factory, scratchpad::scratchpad_client, sticky::unstick,
+    ForgetClient,
+};
+use penrose::{
+    core::State,
+    x::{XConn, XConnExt},
+    Result, Xid,
+};
+use std::collections::HashSet;
+use tracing::{debug, info};
+
+/// Where minimized clients go.
+pub const MINIMIZED_TAG: &str = "minimized";
+
+#[derive(Debug, Default)]
+pub struct Minimized {
+    /// Minimized clients and the tags they came from, oldest first
+    pub stack: Vec<(Xid, String)>,
+}
+
+impl ForgetClient for Minimized {
+    fn forget_client(&mut self, client: Xid) {
+        self.stack.retain(|(c, _)| *c != client);
+    }
+}
+
+/// Clients currently minimized.
+pub fn minimized_clients<X: XConn + 'static>(state: &mut State<X>) -> HashSet<Xid> {
+    minimized_stack(state)
+        .into_iter()
+        .map(|(client, _)| client)
+        .collect()
+}
+
+/// The minimized clients and the tags they came from, oldest first.
+pub fn minimized_stack<X: XConn + 'static>(state: &mut State<X>) -> Vec<(Xid, String)> {
+    let minimized = state.extension_or_default::<Minimized>();
+    let mut minimized = minimized.borrow_mut();
+    // Anything moved off the minimized tag some other way isn't minimized now
+    minimized
+        .stack
+        .retain(|(client, _)| state.client_set.tag_for_client(client) == Some(MINIMIZED_TAG));
+    minimized.stack.clone()
+}
+
+/// Key handler minimizing the focused client.
+pub fn minimize_focused<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = match state.client_set.current_client().cloned() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    // The scratchpad hides itself
+    if scratchpad_client(state) == Some(client) {
+        return Ok(());
+    }
+    let tag = state.client_set.current_tag().to_string();
+    info!(?client, "Minimizing client from {tag}");
+    minimize(client, tag, state)?;
+
+    x.refresh(state)
+}
+
+/// Minimize `client`, to be restored to `tag` later.
+pub fn minimize<X: XConn + 'static>(client: Xid, tag: String, state: &mut State<X>) -> Result<()> {
+    unstick(client, state);
+    if state.client_set.workspace(MINIMIZED_TAG).is_none() {
+        state
+            .client_set
+            .add_workspace(MINIMIZED_TAG, default_layout_factory())?;
+    }
+    state.client_set.move_client_to_tag(&client, MINIMIZED_TAG);
+    state
+        .extension_or_default::<Minimized>()
+        .borrow_mut()
+        .stack
+        .push((client, tag));
+
+    Ok(())
+}
+
+/// Key handler restoring the most recently minimized client to its tag.
+pub fn restore_minimized<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    minimized_clients(state);
+    let popped = state
+        .extension_or_default::<Minimized>()
+        .borrow_mut()
+        .stack
+        .pop();
+    let (client, tag) = match popped {
+        Some(popped) => popped,
+        None => {
+            debug!("Nothing minimized to restore");
+            return Ok(());
+        }
+    };
+
+    info!(?client, "Restoring minimized client to {tag}");
+    // Tags past the fixed ones can have been tidied away in the meantime
+    if state.client_set.workspace(&tag).is_none() {
+        create_tag(state, &tag)?;
+    }
+    state.client_set.move_client_to_tag(&client, &tag);
+    state.client_set.focus_tag(&tag);
+    state.client_set.focus_client(&client);
+
+    x.refresh(state)
+}
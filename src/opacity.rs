@@ -0,0 +1,91 @@
+//! Dimming unfocused windows through `_NET_WM_WINDOW_OPACITY`, so a compositor
+//! like picom can do inactive-dimming without needing its own rules.
+//!
+//! Nothing is touched unless `inactive_opacity` is set under `[appearance]`,
+//! and turning it back off clears the property from everything we set it on
+//! so the compositor's own rules take over again.
+use crate::{get_appearance, ForgetClient};
+use penrose::{
+    core::State,
+    x::{atom::Atom, property::Prop, XConn},
+    Result, Xid,
+};
+use std::collections::HashMap;
+use tracing::debug;
+
+const OPAQUE: u32 = u32::MAX;
+
+#[derive(Debug, Default)]
+pub struct Opacity {
+    /// How opaque unfocused clients are, as a percentage
+    inactive: Option<u8>,
+    /// The opacity each client was last given
+    applied: HashMap<Xid, u32>,
+}
+
+impl ForgetClient for Opacity {
+    fn forget_client(&mut self, client: Xid) {
+        self.applied.remove(&client);
+    }
+}
+
+/// Convert a percentage into the value `_NET_WM_WINDOW_OPACITY` expects.
+fn opacity_value(percent: u8) -> u32 {
+    (OPAQUE as u64 * percent.min(100) as u64 / 100) as u32
+}
+
+/// Startup hook picking up `inactive_opacity` from the config.
+pub fn load_opacity<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    set_inactive_opacity(state, get_appearance().inactive_opacity);
+
+    Ok(())
+}
+
+/// Dim unfocused clients to `percent`, or stop dimming them when `None`.
+pub fn set_inactive_opacity<X: XConn + 'static>(state: &mut State<X>, percent: Option<u8>) {
+    state
+        .extension_or_default::<Opacity>()
+        .borrow_mut()
+        .inactive = percent;
+}
+
+/// Refresh hook giving the focused client full opacity and every other client
+/// the configured inactive opacity.
+pub fn dim_unfocused<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let opacity = state.extension_or_default::<Opacity>();
+    let mut opacity = opacity.borrow_mut();
+    opacity
+        .applied
+        .retain(|client, _| state.client_set.contains(client));
+
+    let inactive = match opacity.inactive {
+        Some(percent) => opacity_value(percent),
+        None => {
+            for (client, _) in opacity.applied.drain() {
+                debug!(?client, "Clearing window opacity");
+                x.delete_prop(client, Atom::NetWmWindowOpacity.as_ref())?;
+            }
+            return Ok(());
+        }
+    };
+
+    let focused = state.client_set.current_client().cloned();
+    for client in state.client_set.clients() {
+        let value = if Some(*client) == focused {
+            OPAQUE
+        } else {
+            inactive
+        };
+        if opacity.applied.get(client) == Some(&value) {
+            continue;
+        }
+        x.set_prop(
+            *client,
+            Atom::NetWmWindowOpacity.as_ref(),
+            Prop::Cardinal(vec![value]),
+        )?;
+        opacity.applied.insert(*client, value);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Putting tags on particular monitors at startup, going by their RandR output
+//! names (e.g. `DP-1`) so the assignment holds up across reboots, unlike screen
+//! indices which depend on the order outputs were found in.
+//!
+//! penrose only knows screens by their geometry, so each output is matched up
+//! with the screen covering the same area as its CRTC.
+use crate::config;
+use penrose::{
+    core::State,
+    pure::geometry::Rect,
+    x::{XConn, XConnExt},
+    Result,
+};
+use std::collections::HashMap;
+use tracing::{debug, info, warn};
+use x11rb::{
+    connection::Connection,
+    protocol::randr::{self, ConnectionExt},
+};
+
+/// Where each connected output is showing, by name.
+fn output_geometries() -> Result<HashMap<String, Rect>> {
+    let (conn, screen) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen].root;
+    let resources = conn.randr_get_screen_resources_current(root)?.reply()?;
+
+    let mut geometries = HashMap::new();
+    for output in resources.outputs {
+        let info = conn
+            .randr_get_output_info(output, resources.config_timestamp)?
+            .reply()?;
+        if info.connection != randr::Connection::CONNECTED || info.crtc == 0 {
+            continue;
+        }
+        let crtc = conn
+            .randr_get_crtc_info(info.crtc, resources.config_timestamp)?
+            .reply()?;
+        let name = String::from_utf8_lossy(&info.name).into_owned();
+        let geometry = Rect::new(
+            crtc.x as u32,
+            crtc.y as u32,
+            crtc.width as u32,
+            crtc.height as u32,
+        );
+        geometries.insert(name, geometry);
+    }
+
+    Ok(geometries)
+}
+
+/// Startup hook showing the tags configured under `[outputs]` on their
+/// monitors.
+pub fn assign_output_tags<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let path = config::config_path();
+    let outputs = match config::load_output_tags(&path) {
+        Ok(outputs) if outputs.is_empty() => return Ok(()),
+        Ok(outputs) => outputs,
+        Err(e) => {
+            warn!("Failed to load output tags from {}: {e}", path.display());
+            return Ok(());
+        }
+    };
+    let geometries = match output_geometries() {
+        Ok(geometries) => geometries,
+        Err(e) => {
+            warn!("Unable to look up outputs, leaving tags where they are: {e}");
+            return Ok(());
+        }
+    };
+
+    let focused_screen = state.client_set.current_screen().index();
+    for (output, tag) in outputs {
+        let geometry = match geometries.get(&output) {
+            Some(geometry) => *geometry,
+            None => {
+                warn!("Output {output} isn't connected, not putting {tag} on it");
+                continue;
+            }
+        };
+        let screen = state
+            .client_set
+            .screens()
+            .find(|screen| screen.geometry() == geometry)
+            .map(|screen| screen.index());
+        match screen {
+            Some(screen) if state.client_set.workspace(&tag).is_some() => {
+                info!("Showing {tag} on {output}");
+                state.client_set.focus_screen(screen);
+                state.client_set.pull_tag_to_screen(&tag);
+            }
+            Some(_) => warn!("No such tag {tag} to show on {output}"),
+            None => debug!(?geometry, "No screen found for output {output}"),
+        }
+    }
+    state.client_set.focus_screen(focused_screen);
+
+    x.refresh(state)
+}
@@ -0,0 +1,129 @@
+//! Switching crowded Monocle workspaces to a layout that shows everything.
+//!
+//! Monocle only shows one window at a time, so once more than the configured
+//! `threshold` pile up on a workspace it switches to the overflow layout, and
+//! back to Monocle once there are few enough again. Changing a workspace's
+//! layout by hand leaves it alone until it's next emptied.
+use crate::{
+    config::{LayoutKind, OverflowConfig},
+    get_overflow_config, ignored_clients, is_hidden_tag, layout_kind_name,
+};
+use penrose::{
+    core::State,
+    x::{XConn, XConnExt},
+    Result,
+};
+use std::collections::{HashMap, HashSet};
+use tracing::debug;
+
+#[derive(Debug, Default)]
+pub struct Overflow {
+    /// Workspaces we switched away from Monocle
+    overflowed: HashSet<String>,
+    /// Workspaces whose layout was changed by something other than us
+    overridden: HashSet<String>,
+    /// The layout each workspace was on as of the last refresh
+    last_layout: HashMap<String, String>,
+    /// Read in on first use and kept until the config is reloaded
+    config: Option<OverflowConfig>,
+}
+
+impl Overflow {
+    /// The layout `tag` should switch to, if any, now that it's on `layout`
+    /// with `count` clients.
+    pub fn update(
+        &mut self,
+        tag: &str,
+        layout: &str,
+        count: usize,
+        threshold: usize,
+        (monocle, target): (&str, &str),
+    ) -> Option<String> {
+        let changed_elsewhere = self
+            .last_layout
+            .get(tag)
+            .map_or(false, |last| last != layout);
+        if count == 0 {
+            self.overridden.remove(tag);
+            self.overflowed.remove(tag);
+        } else if changed_elsewhere {
+            debug!("Layout on {tag} was changed by hand, not overflowing it");
+            self.overridden.insert(tag.to_string());
+            self.overflowed.remove(tag);
+        }
+        if self.overridden.contains(tag) {
+            return None;
+        }
+
+        if layout == monocle && count > threshold {
+            self.overflowed.insert(tag.to_string());
+            Some(target.to_string())
+        } else if layout == target && count <= threshold && self.overflowed.remove(tag) {
+            Some(monocle.to_string())
+        } else {
+            None
+        }
+    }
+
+    /// Note which layout `tag` ended up on.
+    pub fn remember(&mut self, tag: &str, layout: String) {
+        self.last_layout.insert(tag.to_string(), layout);
+    }
+}
+
+/// When crowded workspaces overflow, and to what.
+pub fn overflow_config<X: XConn + 'static>(state: &mut State<X>) -> OverflowConfig {
+    state
+        .extension_or_default::<Overflow>()
+        .borrow_mut()
+        .config
+        .get_or_insert_with(get_overflow_config)
+        .clone()
+}
+
+/// Swap in a freshly loaded overflow config.
+pub fn set_overflow_config<X: XConn + 'static>(state: &mut State<X>, config: OverflowConfig) {
+    state.extension_or_default::<Overflow>().borrow_mut().config = Some(config);
+}
+
+/// Refresh hook switching workspaces between Monocle and the overflow layout
+/// as they fill up and empty out.
+pub fn overflow_crowded_monocle<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let config = overflow_config(state);
+    let threshold = match config.threshold {
+        Some(threshold) => threshold,
+        None => return Ok(()),
+    };
+    let layouts = (
+        layout_kind_name(LayoutKind::Monocle),
+        layout_kind_name(config.layout),
+    );
+    let ignored = ignored_clients(state);
+    let overflow = state.extension_or_default::<Overflow>();
+    let mut overflow = overflow.borrow_mut();
+
+    let mut changed = false;
+    for workspace in state.client_set.workspaces_mut() {
+        let tag = workspace.tag().to_string();
+        if is_hidden_tag(&tag) {
+            continue;
+        }
+        let count = workspace.clients().filter(|c| !ignored.contains(c)).count();
+        let layout = workspace.layout_name();
+        if let Some(next) =
+            overflow.update(&tag, &layout, count, threshold, (&layouts.0, &layouts.1))
+        {
+            debug!("{count} clients on {tag}, switching to {next}");
+            workspace.set_layout_by_name(&next);
+            changed |= workspace.layout_name() != layout;
+        }
+        overflow.remember(&tag, workspace.layout_name());
+    }
+    std::mem::drop(overflow);
+
+    if changed {
+        x.refresh(state)?;
+    }
+
+    Ok(())
+}
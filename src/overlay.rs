@@ -0,0 +1,144 @@
+//! A bare bones override-redirect window listing the alt-tab candidates while
+//! we're task switching. This talks to the X server directly through the
+//! x11rb connection wrapped by [RustConn] since penrose doesn't do any drawing
+//! itself.
+use crate::internal::mark_wm_internal;
+use penrose::{pure::geometry::Rect, x11rb::RustConn, Result};
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{
+        ChangeGCAux, ConfigureWindowAux, ConnectionExt, CreateGCAux, CreateWindowAux, Rectangle,
+        StackMode, WindowClass,
+    },
+    COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT,
+};
+
+const FONT: &[u8] = b"fixed";
+const WIDTH: u16 = 600;
+const ROW_HEIGHT: u16 = 20;
+const PADDING: u16 = 6;
+const BACKGROUND: u32 = 0x282828;
+const FOREGROUND: u32 = 0xebdbb2;
+const SELECTED: u32 = 0x458588;
+
+/// The overlay's X resources, which only exist while it's being shown.
+#[derive(Debug, Default)]
+pub struct AltTabOverlay {
+    window: Option<u32>,
+    gc: u32,
+    font: u32,
+}
+
+impl AltTabOverlay {
+    /// Show (or redraw) the overlay centered on `screen`. Each row is a window
+    /// title along with whether it's the current selection.
+    pub fn show(&mut self, conn: &RustConn, screen: Rect, rows: &[(String, bool)]) -> Result<()> {
+        let conn = conn.connection();
+        let height = ROW_HEIGHT * rows.len() as u16 + PADDING * 2;
+        let x = screen.x as i32 + (screen.w as i32 - WIDTH as i32) / 2;
+        let y = screen.y as i32 + (screen.h as i32 - height as i32) / 2;
+
+        let window = match self.window {
+            Some(window) => window,
+            None => {
+                let window = conn.generate_id()?;
+                conn.create_window(
+                    COPY_DEPTH_FROM_PARENT,
+                    window,
+                    conn.setup().roots[0].root,
+                    x as i16,
+                    y as i16,
+                    WIDTH,
+                    height,
+                    0,
+                    WindowClass::INPUT_OUTPUT,
+                    COPY_FROM_PARENT,
+                    &CreateWindowAux::new()
+                        .override_redirect(1)
+                        .background_pixel(BACKGROUND),
+                )?;
+                mark_wm_internal(conn, window)?;
+                self.font = conn.generate_id()?;
+                conn.open_font(self.font, FONT)?;
+                self.gc = conn.generate_id()?;
+                conn.create_gc(self.gc, window, &CreateGCAux::new().font(self.font))?;
+                conn.map_window(window)?;
+                self.window = Some(window);
+                window
+            }
+        };
+
+        conn.configure_window(
+            window,
+            &ConfigureWindowAux::new()
+                .x(x)
+                .y(y)
+                .width(WIDTH as u32)
+                .height(height as u32)
+                .stack_mode(StackMode::ABOVE),
+        )?;
+        conn.change_gc(self.gc, &ChangeGCAux::new().foreground(BACKGROUND))?;
+        conn.poly_fill_rectangle(
+            window,
+            self.gc,
+            &[Rectangle {
+                x: 0,
+                y: 0,
+                width: WIDTH,
+                height,
+            }],
+        )?;
+
+        for (index, (title, selected)) in rows.iter().enumerate() {
+            let row_y = (PADDING + ROW_HEIGHT * index as u16) as i16;
+            let background = if *selected { SELECTED } else { BACKGROUND };
+            conn.change_gc(self.gc, &ChangeGCAux::new().foreground(background))?;
+            conn.poly_fill_rectangle(
+                window,
+                self.gc,
+                &[Rectangle {
+                    x: 0,
+                    y: row_y,
+                    width: WIDTH,
+                    height: ROW_HEIGHT,
+                }],
+            )?;
+            conn.change_gc(
+                self.gc,
+                &ChangeGCAux::new()
+                    .foreground(FOREGROUND)
+                    .background(background),
+            )?;
+            // The core font only knows about Latin-1, and the request caps out
+            // at 255 bytes.
+            let text = title
+                .chars()
+                .filter(|c| c.is_ascii() && !c.is_ascii_control())
+                .take(255)
+                .collect::<String>();
+            conn.image_text8(
+                window,
+                self.gc,
+                PADDING as i16,
+                row_y + ROW_HEIGHT as i16 - PADDING as i16,
+                text.as_bytes(),
+            )?;
+        }
+        conn.flush()?;
+
+        Ok(())
+    }
+
+    /// Destroy the overlay window if it's currently up.
+    pub fn hide(&mut self, conn: &RustConn) -> Result<()> {
+        let conn = conn.connection();
+        if let Some(window) = self.window.take() {
+            conn.free_gc(self.gc)?;
+            conn.close_font(self.font)?;
+            conn.destroy_window(window)?;
+            conn.flush()?;
+        }
+
+        Ok(())
+    }
+}
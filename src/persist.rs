@@ -0,0 +1,310 @@
+//! Saving the alt-tab order to disk so it survives restarting the WM.
+//!
+//! This is best-effort: clients are saved by `Xid` along with their `WM_CLASS`.
+//! When only the WM restarts the ids survive and everything lines up, but if
+//! the X server went away too the ids will have been handed out again and
+//! we fall back to matching clients up by class, in the order they were saved.
+//! Anything we can't match just goes to the back as usual.
+//!
+//! Restored clients keep their saved order until the user presses something,
+//! since managing the existing clients on startup focuses each in turn.
+//!
+//! The order is written out when restarting or exiting, and otherwise at most
+//! every [SAVE_INTERVAL] so that a crash only loses the last little while.
+//! Failing to write it is logged and otherwise ignored.
+//!
+//! Restarting also saves which tag every client was on, hidden tags included,
+//! so they can be put back once the new process has adopted them. That only
+//! makes sense while the ids are still good, so it's used once and removed.
+use crate::{get_app_name, RecentClients};
+use penrose::{
+    core::State,
+    x::{event::XEvent, XConn},
+    Error, Result, Xid,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+pub fn state_path() -> PathBuf {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_default();
+    state_home.join("wendy").join("recent_clients.toml")
+}
+
+fn placements_path() -> PathBuf {
+    state_path().with_file_name("placements.toml")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SavedClient {
+    id: u32,
+    class: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SavedRecentClients {
+    recent: Vec<SavedClient>,
+    chronological: Vec<SavedClient>,
+}
+
+/// Where a client was when we restarted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedPlacement {
+    pub id: u32,
+    pub tag: String,
+    /// For a client on a hidden tag, the tag it goes back to
+    pub from: Option<String>,
+    /// For a swallowed terminal, the client that swallowed it
+    pub swallowed_by: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct SavedPlacements {
+    clients: Vec<SavedPlacement>,
+}
+
+/// How often the order is saved while we're running.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What we last wrote out, so we only hit the disk when something changed.
+#[derive(Debug, Default)]
+struct LastSaved {
+    recent: Vec<Xid>,
+    chronological: Vec<Xid>,
+    /// When we last tried to, whether or not it worked
+    attempted: Option<Instant>,
+}
+
+/// Saved order for one of the lists, and which clients have claimed which spot.
+#[derive(Debug, Default)]
+struct SavedOrder {
+    saved: Vec<SavedClient>,
+    ranks: HashMap<Xid, usize>,
+}
+
+impl SavedOrder {
+    fn new(saved: Vec<SavedClient>) -> Self {
+        Self {
+            saved,
+            ranks: HashMap::new(),
+        }
+    }
+
+    /// Find a spot for `client`, preferring the same id and falling back to the
+    /// first unclaimed client of the same class.
+    fn rank<X: XConn>(&mut self, client: Xid, x: &X) -> Option<usize> {
+        if let Some(rank) = self.ranks.get(&client) {
+            return Some(*rank);
+        }
+        let class = get_app_name(client, x);
+        let claimed = self.ranks.values().cloned().collect::<HashSet<_>>();
+        let mut unclaimed = self
+            .saved
+            .iter()
+            .enumerate()
+            .filter(|(rank, saved)| !claimed.contains(rank) && saved.class == class);
+        let rank = unclaimed
+            .clone()
+            .find(|(_, saved)| saved.id == *client)
+            .or_else(|| unclaimed.next())
+            .map(|(rank, _)| rank)?;
+        self.ranks.insert(client, rank);
+        Some(rank)
+    }
+
+    /// Put `clients` in their saved order. Clients that weren't saved keep their
+    /// relative order at the back.
+    fn sort<X: XConn>(&mut self, clients: &mut [Xid], x: &X) {
+        let ranks = clients
+            .iter()
+            .map(|client| (*client, self.rank(*client, x).unwrap_or(usize::MAX)))
+            .collect::<HashMap<_, _>>();
+        clients.sort_by_key(|client| ranks[client]);
+    }
+}
+
+/// The order read back in at startup, waiting to be applied.
+#[derive(Debug, Default)]
+pub struct PendingRestore {
+    restoring: bool,
+    recent: SavedOrder,
+    chronological: SavedOrder,
+}
+
+/// Startup hook reading in the saved order.
+pub fn load_recent_clients<X: XConn + 'static>(state: &mut State<X>, _x: &X) -> Result<()> {
+    let path = state_path();
+    let saved: SavedRecentClients = match fs::read_to_string(&path) {
+        Ok(contents) => match toml::from_str(&contents) {
+            Ok(saved) => saved,
+            Err(e) => {
+                warn!("Ignoring unreadable {}: {e}", path.display());
+                return Ok(());
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            warn!("Unable to read {}: {e}", path.display());
+            return Ok(());
+        }
+    };
+    debug!(?saved, "Restoring recent clients");
+
+    state.add_extension(PendingRestore {
+        restoring: true,
+        recent: SavedOrder::new(saved.recent),
+        chronological: SavedOrder::new(saved.chronological),
+    });
+
+    Ok(())
+}
+
+/// Reorder `recent_clients` to match what we saved. Returns whether we're
+/// still restoring, in which case focus changes shouldn't be committed.
+pub fn restore_recent_clients<X: XConn + 'static>(
+    state: &mut State<X>,
+    x: &X,
+    recent_clients: &mut RecentClients,
+) -> bool {
+    let pending = state.extension_or_default::<PendingRestore>();
+    let mut pending = pending.borrow_mut();
+    if !pending.restoring {
+        return false;
+    }
+    pending.recent.sort(&mut recent_clients.recent_clients, x);
+    pending
+        .chronological
+        .sort(&mut recent_clients.chronological_clients, x);
+
+    true
+}
+
+/// Event hook handing control of the order back once the user does anything.
+pub fn finish_restore<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    _x: &X,
+) -> Result<bool> {
+    if matches!(event, XEvent::KeyPress(_) | XEvent::MouseEvent(_)) {
+        let pending = state.extension_or_default::<PendingRestore>();
+        let mut pending = pending.borrow_mut();
+        if pending.restoring {
+            debug!("Done restoring recent clients");
+            *pending = PendingRestore::default();
+        }
+    }
+
+    Ok(true)
+}
+
+/// Refresh hook saving the order every [SAVE_INTERVAL] if it's changed.
+pub fn save_recent_clients_periodically<X: XConn + 'static>(
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    let due = state
+        .extension_or_default::<LastSaved>()
+        .borrow()
+        .attempted
+        .map_or(true, |attempted| attempted.elapsed() >= SAVE_INTERVAL);
+    if due {
+        save_recent_clients(state, x);
+    }
+
+    Ok(())
+}
+
+/// Write the order out now if it's changed, e.g. before restarting or exiting.
+pub fn save_recent_clients<X: XConn + 'static>(state: &mut State<X>, x: &X) {
+    let recent_clients = state.extension_or_default::<RecentClients>();
+    let recent_clients = recent_clients.borrow();
+    let last_saved = state.extension_or_default::<LastSaved>();
+    let mut last_saved = last_saved.borrow_mut();
+    if last_saved.recent == recent_clients.recent_clients
+        && last_saved.chronological == recent_clients.chronological_clients
+    {
+        return;
+    }
+
+    // Even a failed attempt waits out the interval, rather than retrying (and
+    // warning) on every refresh
+    last_saved.attempted = Some(Instant::now());
+    let describe = |clients: &[Xid]| {
+        clients
+            .iter()
+            .map(|client| SavedClient {
+                id: **client,
+                class: get_app_name(*client, x),
+            })
+            .collect()
+    };
+    let saved = SavedRecentClients {
+        recent: describe(&recent_clients.recent_clients),
+        chronological: describe(&recent_clients.chronological_clients),
+    };
+    if let Err(e) = write_recent_clients(&saved) {
+        warn!("Unable to save the alt-tab order: {e}");
+        return;
+    }
+
+    last_saved.recent = recent_clients.recent_clients.clone();
+    last_saved.chronological = recent_clients.chronological_clients.clone();
+}
+
+fn write_recent_clients(saved: &SavedRecentClients) -> Result<()> {
+    let path = state_path();
+    let contents = toml::to_string(saved)
+        .map_err(|e| Error::Custom(format!("unable to serialize recent clients: {e}")))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, contents)?;
+
+    Ok(())
+}
+
+/// Write out where every client is, for [take_saved_placements] to pick up
+/// after restarting.
+pub fn save_placements(clients: Vec<SavedPlacement>) -> Result<()> {
+    let path = placements_path();
+    let contents = toml::to_string(&SavedPlacements { clients })
+        .map_err(|e| Error::Custom(format!("unable to serialize placements: {e}")))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, contents)?;
+
+    Ok(())
+}
+
+/// Read back the placements saved before restarting, if we just did.
+pub fn take_saved_placements() -> Vec<SavedPlacement> {
+    let path = placements_path();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            warn!("Unable to read {}: {e}", path.display());
+            return Vec::new();
+        }
+    };
+    // The ids mean nothing after the next time the X server starts
+    if let Err(e) = fs::remove_file(&path) {
+        warn!("Unable to remove {}: {e}", path.display());
+    }
+    match toml::from_str::<SavedPlacements>(&contents) {
+        Ok(saved) => saved.clients,
+        Err(e) => {
+            warn!("Ignoring unreadable {}: {e}", path.display());
+            Vec::new()
+        }
+    }
+}
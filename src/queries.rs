@@ -0,0 +1,91 @@
+//! Queries for matching clients beyond the plain string matches penrose ships
+//! with.
+use crate::{get_app_classes, get_title};
+use penrose::{
+    x::{property::Prop, query::Query, XConn},
+    Error, Result, Xid,
+};
+use regex::Regex;
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// Matches if any of a client's `WM_CLASS` strings match the regex.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ClassNameRegex(Regex);
+
+impl ClassNameRegex {
+    pub fn new(pattern: &str) -> Result<Self> {
+        Regex::new(pattern)
+            .map(Self)
+            .map_err(|e| Error::Custom(format!("invalid class regex {pattern:?}: {e}")))
+    }
+}
+
+impl TryFrom<String> for ClassNameRegex {
+    type Error = Error;
+
+    fn try_from(pattern: String) -> Result<Self> {
+        Self::new(&pattern)
+    }
+}
+
+impl<X: XConn> Query<X> for ClassNameRegex {
+    fn run(&self, id: Xid, x: &X) -> Result<bool> {
+        Ok(get_app_classes(id, x)
+            .iter()
+            .any(|class| self.0.is_match(class)))
+    }
+}
+
+/// Matches if the client's title contains the given string.
+///
+/// Titles change over time (a browser's follows the open tab) but this is only
+/// checked when the client is first managed, so a window isn't moved about if
+/// its title stops matching later on.
+#[derive(Debug, Clone, Copy)]
+pub struct TitleContains<'a>(pub &'a str);
+
+impl<X: XConn> Query<X> for TitleContains<'_> {
+    fn run(&self, id: Xid, x: &X) -> Result<bool> {
+        Ok(get_title(id, x).map_or(false, |title| title.contains(self.0)))
+    }
+}
+
+/// Matches if the executable of the process owning the client (going by
+/// `_NET_WM_PID`) has the given name, for apps whose class and name don't say
+/// much. Falls back to the process's `comm` if the executable can't be read.
+///
+/// Clients without a pid, or whose process has already gone away, don't match.
+#[derive(Debug, Clone, Copy)]
+pub struct PidExe<'a>(pub &'a str);
+
+/// The pid of the process `id` belongs to, from `_NET_WM_PID`.
+pub fn client_pid<X: XConn>(id: Xid, x: &X) -> Result<Option<u32>> {
+    Ok(match x.get_prop(id, "_NET_WM_PID")? {
+        Some(Prop::Cardinal(pids)) => pids.first().cloned(),
+        _ => None,
+    })
+}
+
+/// The name of the executable running as `pid`.
+pub fn process_name(pid: u32) -> Option<String> {
+    let proc_dir = Path::new("/proc").join(pid.to_string());
+    match fs::read_link(proc_dir.join("exe")) {
+        Ok(exe) => exe
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned()),
+        // Another user's processes can't be followed, but their comm can
+        Err(_) => fs::read_to_string(proc_dir.join("comm"))
+            .ok()
+            .map(|comm| comm.trim_end().to_string()),
+    }
+}
+
+impl<X: XConn> Query<X> for PidExe<'_> {
+    fn run(&self, id: Xid, x: &X) -> Result<bool> {
+        Ok(client_pid(id, x)?
+            .and_then(process_name)
+            .map_or(false, |name| name == self.0))
+    }
+}
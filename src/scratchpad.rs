@@ -0,0 +1,187 @@
+//! A dropdown terminal that floats over whatever workspace is focused when it's
+//! toggled on, and lives on a hidden tag the rest of the time.
+use crate::{
+    default_layout_factory, get_tag_for_client, sticky::unstick, ForgetClient, RecentClients,
+};
+use penrose::{
+    core::State,
+    pure::geometry::Rect,
+    util,
+    x::{
+        query::{ClassName, Query},
+        XConn, XConnExt,
+    },
+    Result, Xid,
+};
+use tracing::{debug, info};
+
+/// Where the scratchpad goes when it's hidden.
+pub const SCRATCHPAD_TAG: &str = "scratchpad";
+const SCRATCHPAD_CLASS: &str = "wendy-scratch";
+const SCRATCHPAD_COMMAND: &str = "alacritty --class wendy-scratch";
+
+#[derive(Debug, Default)]
+pub struct Scratchpad {
+    client: Option<Xid>,
+}
+
+impl ForgetClient for Scratchpad {
+    fn forget_client(&mut self, client: Xid) {
+        if self.client == Some(client) {
+            self.client = None;
+        }
+    }
+}
+
+pub fn is_scratchpad<X: XConn>(client: Xid, x: &X) -> bool {
+    ClassName(SCRATCHPAD_CLASS).run(client, x).unwrap_or(false)
+}
+
+/// The scratchpad client, if it's still around.
+pub fn scratchpad_client<X: XConn + 'static>(state: &mut State<X>) -> Option<Xid> {
+    let client = state.extension_or_default::<Scratchpad>().borrow().client;
+    client.filter(|client| state.client_set.contains(client))
+}
+
+/// Drop the scratchpad down from the top of the current screen.
+fn float_scratchpad<X: XConn + 'static>(client: Xid, state: &mut State<X>) -> Result<()> {
+    let screen = state.client_set.current_screen().geometry();
+    let w = screen.w * 2 / 3;
+    let h = screen.h / 2;
+    let r = Rect::new(screen.x + (screen.w - w) / 2, screen.y, w, h);
+    state.client_set.float(client, r)
+}
+
+/// Manage hook picking up the scratchpad terminal when it first appears.
+pub fn manage_scratchpad<X: XConn + 'static>(
+    client: Xid,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    if !is_scratchpad(client, x) {
+        return Ok(());
+    }
+    state
+        .extension_or_default::<Scratchpad>()
+        .borrow_mut()
+        .client = Some(client);
+    float_scratchpad(client, state)?;
+    state.client_set.focus_client(&client);
+
+    Ok(())
+}
+
+fn hide<X: XConn + 'static>(client: Xid, state: &mut State<X>) -> Result<()> {
+    unstick(client, state);
+    if state.client_set.workspace(SCRATCHPAD_TAG).is_none() {
+        state
+            .client_set
+            .add_workspace(SCRATCHPAD_TAG, default_layout_factory())?;
+    }
+    state.client_set.move_client_to_tag(&client, SCRATCHPAD_TAG);
+    Ok(())
+}
+
+/// Make the focused client the scratchpad and tuck it away. Whatever was the
+/// scratchpad before becomes a normal window on the current workspace.
+pub fn stash_focused<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = match state.client_set.current_client().cloned() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    if let Some(previous) = scratchpad_client(state).filter(|previous| *previous != client) {
+        let tag = state.client_set.current_tag().to_string();
+        state.client_set.move_client_to_tag(&previous, &tag);
+        state.client_set.sink(&previous);
+    }
+
+    tuck_away(client, state)?;
+    x.refresh(state)
+}
+
+/// Make `client` the scratchpad, hidden until it's next toggled.
+pub fn tuck_away<X: XConn + 'static>(client: Xid, state: &mut State<X>) -> Result<()> {
+    state
+        .extension_or_default::<Scratchpad>()
+        .borrow_mut()
+        .client = Some(client);
+    hide(client, state)
+}
+
+/// Show the scratchpad on the current workspace, or stash it away if it's
+/// already here. Spawns it if it doesn't exist yet.
+pub fn toggle_scratchpad<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = match scratchpad_client(state) {
+        Some(client) => client,
+        None => return util::spawn(SCRATCHPAD_COMMAND),
+    };
+
+    if state
+        .client_set
+        .current_workspace()
+        .clients()
+        .any(|existing| *existing == client)
+    {
+        hide(client, state)?;
+    } else {
+        let tag = state.client_set.current_tag().to_string();
+        state.client_set.move_client_to_tag(&client, &tag);
+        float_scratchpad(client, state)?;
+        state.client_set.focus_client(&client);
+    }
+
+    x.refresh(state)
+}
+
+/// Key handler turning the scratchpad, while it's showing, into a normal window
+/// placed like any new one. The next toggle starts a fresh scratchpad.
+pub fn release_scratchpad<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = match scratchpad_client(state) {
+        Some(client)
+            if state
+                .client_set
+                .current_workspace()
+                .clients()
+                .any(|c| *c == client) =>
+        {
+            client
+        }
+        _ => {
+            debug!("No scratchpad showing to release");
+            return Ok(());
+        }
+    };
+
+    state
+        .extension_or_default::<Scratchpad>()
+        .borrow_mut()
+        .client = None;
+    state.client_set.sink(&client);
+    let (tag, reason) = get_tag_for_client(client, state, x)?;
+    info!(?client, ?reason, "Releasing the scratchpad to {tag}");
+    state.client_set.move_client_to_tag(&client, &tag);
+    state.client_set.focus_tag(&tag);
+    state.client_set.focus_client(&client);
+    state
+        .extension_or_default::<RecentClients>()
+        .borrow_mut()
+        .add(client, true);
+
+    x.refresh(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_scratchpad_is_forgotten_once_destroyed() {
+        let (gone, kept) = (Xid::from(1), Xid::from(2));
+        let mut scratchpad = Scratchpad { client: Some(gone) };
+
+        scratchpad.forget_client(kept);
+        assert_eq!(scratchpad.client, Some(gone));
+        scratchpad.forget_client(gone);
+        assert_eq!(scratchpad.client, None);
+    }
+}
@@ -0,0 +1,62 @@
+//! Remembering which tag each screen was showing, so that unplugging a monitor
+//! and plugging it back in puts things back where they were.
+//!
+//! Screens are told apart by their index and layouts by how many screens there
+//! are, so this is best-effort: swapping one monitor for another, or plugging
+//! them back in a different order, restores the old tags all the same.
+use penrose::{
+    core::State,
+    x::{XConn, XConnExt},
+    Result,
+};
+use std::collections::HashMap;
+use tracing::info;
+
+#[derive(Debug, Default)]
+pub struct ScreenTags {
+    screen_count: usize,
+    /// The tag on each screen, by how many screens there were
+    tags: HashMap<usize, Vec<String>>,
+}
+
+/// Refresh hook keeping track of the tag on each screen, and putting them back
+/// when we go back to a screen count we've seen before.
+pub fn remember_screen_tags<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let screen_count = state.client_set.screens().count();
+    let screen_tags = state.extension_or_default::<ScreenTags>();
+    let mut screen_tags = screen_tags.borrow_mut();
+    let mut screens = state.client_set.screens().collect::<Vec<_>>();
+    screens.sort_by_key(|screen| screen.index());
+    let tags = screens
+        .iter()
+        .map(|screen| screen.workspace.tag().to_string())
+        .collect::<Vec<_>>();
+
+    if screen_tags.screen_count == screen_count {
+        screen_tags.tags.insert(screen_count, tags);
+        return Ok(());
+    }
+    let previous_count = std::mem::replace(&mut screen_tags.screen_count, screen_count);
+    // Nothing to restore on startup
+    if previous_count == 0 {
+        screen_tags.tags.insert(screen_count, tags);
+        return Ok(());
+    }
+    let saved = match screen_tags.tags.get(&screen_count) {
+        Some(saved) => saved.clone(),
+        None => return Ok(()),
+    };
+    std::mem::drop(screen_tags);
+
+    info!("Screen count changed from {previous_count} to {screen_count}, restoring {saved:?}");
+    let current_screen = state.client_set.current_screen().index();
+    for (index, tag) in saved.iter().enumerate() {
+        if state.client_set.workspace(tag).is_some() && tags.get(index) != Some(tag) {
+            state.client_set.focus_screen(index);
+            state.client_set.pull_tag_to_screen(tag);
+        }
+    }
+    state.client_set.focus_screen(current_screen);
+
+    x.refresh(state)
+}
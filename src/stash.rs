@@ -0,0 +1,122 @@
+//! A "read it later" stash for windows to come back to.
+//!
+//! `M-y` puts the focused window in the stash, a hidden tag of its own kept
+//! apart from minimized windows. `M-S-y` brings the oldest stashed window out
+//! onto the focused tag, and pressing it again straight away puts that one
+//! back at the end and brings out the next, so the stash can be cycled through.
+//! Stashed windows are left out of the alt-tab list and placement, and listed
+//! in the IPC status.
+use crate::{default_layout_factory, scratchpad::scratchpad_client, sticky::unstick, ForgetClient};
+use penrose::{
+    core::State,
+    x::{XConn, XConnExt},
+    Result, Xid,
+};
+use tracing::{debug, info};
+
+/// Where stashed clients go.
+pub const STASH_TAG: &str = "later";
+
+#[derive(Debug, Default)]
+pub struct Stash {
+    /// Stashed clients, oldest first
+    pub clients: Vec<Xid>,
+    /// The client last brought out, which goes back if we cycle on from it
+    showing: Option<Xid>,
+}
+
+impl ForgetClient for Stash {
+    fn forget_client(&mut self, client: Xid) {
+        self.clients.retain(|c| *c != client);
+        if self.showing == Some(client) {
+            self.showing = None;
+        }
+    }
+}
+
+/// Clients currently stashed, oldest first.
+pub fn stashed_clients<X: XConn + 'static>(state: &mut State<X>) -> Vec<Xid> {
+    let stash = state.extension_or_default::<Stash>();
+    let mut stash = stash.borrow_mut();
+    // Anything moved out some other way isn't stashed now
+    stash
+        .clients
+        .retain(|client| state.client_set.tag_for_client(client) == Some(STASH_TAG));
+    stash.clients.clone()
+}
+
+/// Put `client` at the end of the stash.
+pub fn stash<X: XConn + 'static>(client: Xid, state: &mut State<X>) -> Result<()> {
+    unstick(client, state);
+    if state.client_set.workspace(STASH_TAG).is_none() {
+        state
+            .client_set
+            .add_workspace(STASH_TAG, default_layout_factory())?;
+    }
+    state.client_set.move_client_to_tag(&client, STASH_TAG);
+    state
+        .extension_or_default::<Stash>()
+        .borrow_mut()
+        .clients
+        .push(client);
+
+    Ok(())
+}
+
+/// Key handler stashing the focused client for later.
+pub fn stash_focused<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = match state.client_set.current_client().cloned() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    // The scratchpad hides itself
+    if scratchpad_client(state) == Some(client) {
+        return Ok(());
+    }
+
+    info!(?client, "Stashing client for later");
+    stash(client, state)?;
+
+    x.refresh(state)
+}
+
+/// Key handler bringing the oldest stashed client onto the focused tag, putting
+/// back the one brought out last if it's still focused.
+pub fn cycle_stash<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let focused = state.client_set.current_client().cloned();
+    let showing = state
+        .extension_or_default::<Stash>()
+        .borrow_mut()
+        .showing
+        .take();
+    if let Some(showing) = showing.filter(|showing| Some(*showing) == focused) {
+        debug!(client = ?showing, "Putting stashed client back");
+        stash(showing, state)?;
+    }
+
+    stashed_clients(state);
+    let next = {
+        let stash = state.extension_or_default::<Stash>();
+        let mut stash = stash.borrow_mut();
+        if stash.clients.is_empty() {
+            None
+        } else {
+            Some(stash.clients.remove(0))
+        }
+    };
+    let client = match next {
+        Some(client) => client,
+        None => {
+            debug!("Nothing stashed");
+            return x.refresh(state);
+        }
+    };
+
+    info!(?client, "Bringing out stashed client");
+    let tag = state.client_set.current_tag().to_string();
+    state.client_set.move_client_to_tag(&client, &tag);
+    state.client_set.focus_client(&client);
+    state.extension_or_default::<Stash>().borrow_mut().showing = Some(client);
+
+    x.refresh(state)
+}
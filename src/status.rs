@@ -0,0 +1,168 @@
+//! A snapshot of the WM's state for status bars, served as JSON by the IPC
+//! `status` command.
+//!
+//! Bar scripts depend on the shape of this, so anything other than adding a
+//! field needs [STATUS_VERSION] bumping.
+use crate::{
+    get_window_title, is_hidden_tag, minimize::minimized_clients, stash::stashed_clients,
+    tag_names::display_name, tag_order, urgent::urgent_clients, RecentClients,
+};
+use penrose::{
+    core::{ClientSet, State},
+    x::XConn,
+    Xid,
+};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+pub const STATUS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Status {
+    pub version: u32,
+    pub focused_tag: String,
+    pub focused_client: Option<u32>,
+    /// Visible workspaces in tag order, including empty ones.
+    pub workspaces: Vec<WorkspaceStatus>,
+    /// Client ids, most recently focused first.
+    pub recent_clients: Vec<u32>,
+    /// Clients asking for attention.
+    pub urgent_clients: Vec<u32>,
+    /// How many clients are minimized.
+    pub minimized_count: usize,
+    /// Clients stashed for later, oldest first.
+    pub stash: Vec<ClientStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WorkspaceStatus {
+    pub tag: String,
+    /// What to show for the tag, which is the tag itself unless it's been
+    /// renamed.
+    pub name: String,
+    /// Index of the screen showing this workspace, if any.
+    pub screen: Option<usize>,
+    pub client_count: usize,
+    pub clients: Vec<ClientStatus>,
+    /// Whether any of the clients are asking for attention.
+    pub urgent: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClientStatus {
+    pub id: u32,
+    pub title: String,
+    pub urgent: bool,
+}
+
+pub fn status<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Status {
+    let recent_clients = state
+        .extension_or_default::<RecentClients>()
+        .borrow()
+        .recent_clients
+        .iter()
+        .map(|client| **client)
+        .collect();
+    let urgent = urgent_clients(state);
+    let minimized_count = minimized_clients(state).len();
+    let stash = stashed_clients(state)
+        .into_iter()
+        .map(|client| ClientStatus {
+            id: *client,
+            title: get_window_title(client, x),
+            urgent: urgent.contains(&client),
+        })
+        .collect();
+
+    let screens = state
+        .client_set
+        .screens()
+        .map(|screen| (screen.workspace.tag().to_string(), screen.index()))
+        .collect::<HashMap<_, _>>();
+    let mut workspaces = state
+        .client_set
+        .workspaces()
+        .filter(|ws| !is_hidden_tag(ws.tag()))
+        .map(|ws| {
+            let clients = ws
+                .clients()
+                .map(|client| ClientStatus {
+                    id: **client,
+                    title: get_window_title(*client, x),
+                    urgent: urgent.contains(client),
+                })
+                .collect::<Vec<_>>();
+            WorkspaceStatus {
+                tag: ws.tag().to_string(),
+                name: String::new(),
+                screen: screens.get(ws.tag()).copied(),
+                client_count: clients.len(),
+                urgent: clients.iter().any(|client| client.urgent),
+                clients,
+            }
+        })
+        .collect::<Vec<_>>();
+    workspaces.sort_by_key(|ws| tag_order(&ws.tag));
+    for ws in workspaces.iter_mut() {
+        ws.name = display_name(state, &ws.tag);
+    }
+
+    Status {
+        version: STATUS_VERSION,
+        focused_tag: state.client_set.current_tag().to_string(),
+        focused_client: state.client_set.current_client().map(|client| **client),
+        workspaces,
+        recent_clients,
+        urgent_clients: urgent.iter().map(|client| **client).collect(),
+        minimized_count,
+        stash,
+    }
+}
+
+/// The parts of the state a bar cares about, kept so we only push an update
+/// when one of them changes.
+#[derive(Debug, Default)]
+pub struct Fingerprint {
+    tag: String,
+    focus: Option<Xid>,
+    counts: Vec<(String, usize)>,
+    urgent: HashSet<Xid>,
+    names: HashMap<String, String>,
+}
+
+impl Fingerprint {
+    /// Bring this up to date with `client_set`, the `urgent` clients and the
+    /// tags' display `names`, returning whether anything changed. Nothing is
+    /// allocated unless it did.
+    pub fn update(
+        &mut self,
+        client_set: &ClientSet,
+        urgent: &HashSet<Xid>,
+        names: &HashMap<String, String>,
+    ) -> bool {
+        let focus = client_set.current_client().copied();
+        let unchanged = self.tag == client_set.current_tag()
+            && self.focus == focus
+            && self.urgent == *urgent
+            && self.names == *names
+            && self.counts.len() == client_set.workspaces().count()
+            && self
+                .counts
+                .iter()
+                .zip(client_set.workspaces())
+                .all(|((tag, count), ws)| tag == ws.tag() && *count == ws.clients().count());
+        if unchanged {
+            return false;
+        }
+
+        self.tag = client_set.current_tag().to_string();
+        self.focus = focus;
+        self.urgent = urgent.clone();
+        self.names = names.clone();
+        self.counts = client_set
+            .workspaces()
+            .map(|ws| (ws.tag().to_string(), ws.clients().count()))
+            .collect();
+        true
+    }
+}
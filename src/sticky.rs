@@ -0,0 +1,89 @@
+//! Sticky clients follow you around, moving to whichever workspace is focused.
+//!
+//! They're handy for a music player or a dashboard you always want in view.
+//! Being everywhere, they don't count towards a workspace being occupied and
+//! are skipped when alt-tabbing.
+use crate::ForgetClient;
+use penrose::{
+    core::State,
+    x::{XConn, XConnExt},
+    Result, Xid,
+};
+use std::collections::HashSet;
+use tracing::info;
+
+#[derive(Debug, Default)]
+pub struct StickyClients {
+    pub clients: HashSet<Xid>,
+}
+
+impl ForgetClient for StickyClients {
+    fn forget_client(&mut self, client: Xid) {
+        self.clients.remove(&client);
+    }
+}
+
+/// Clients currently sticky.
+pub fn sticky_clients<X: XConn + 'static>(state: &mut State<X>) -> HashSet<Xid> {
+    let sticky = state.extension_or_default::<StickyClients>();
+    let mut sticky = sticky.borrow_mut();
+    sticky
+        .clients
+        .retain(|client| state.client_set.contains(client));
+    sticky.clients.clone()
+}
+
+/// Stop `client` being sticky, returning whether it was. Anything being put
+/// away on a hidden tag needs this, or it follows focus straight back out.
+pub fn unstick<X: XConn + 'static>(client: Xid, state: &mut State<X>) -> bool {
+    let sticky = state.extension_or_default::<StickyClients>();
+    let was_sticky = sticky.borrow_mut().clients.remove(&client);
+    if was_sticky {
+        info!(?client, "Client is no longer sticky");
+    }
+    was_sticky
+}
+
+/// Make the focused client sticky, or stop it being sticky if it already is.
+pub fn toggle_sticky<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = match state.client_set.current_client().cloned() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    let sticky = state.extension_or_default::<StickyClients>();
+    let mut sticky = sticky.borrow_mut();
+    if sticky.clients.remove(&client) {
+        info!(?client, "Client is no longer sticky");
+    } else {
+        info!(?client, "Client is now sticky");
+        sticky.clients.insert(client);
+    }
+    std::mem::drop(sticky);
+
+    x.refresh(state)
+}
+
+/// Refresh hook bringing sticky clients along to the focused workspace. This
+/// needs to run after `backfill_gaps`, which can move them off it.
+pub fn follow_focus<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let tag = state.client_set.current_tag().to_string();
+    let stragglers = sticky_clients(state)
+        .into_iter()
+        .filter(|client| state.client_set.tag_for_client(client) != Some(tag.as_str()))
+        .collect::<Vec<_>>();
+    if stragglers.is_empty() {
+        return Ok(());
+    }
+
+    // Moving a client onto the focused workspace focuses it, which we don't
+    // want to happen every time the tag changes.
+    let focus = state.client_set.current_client().cloned();
+    for client in stragglers {
+        state.client_set.move_client_to_tag(&client, &tag);
+    }
+    if let Some(focus) = focus {
+        state.client_set.focus_client(&focus);
+    }
+
+    x.refresh(state)
+}
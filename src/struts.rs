@@ -0,0 +1,262 @@
+//! Reserving space for docks such as external bars and panels.
+//!
+//! penrose leaves `_NET_WM_STRUT`/`_NET_WM_STRUT_PARTIAL` alone, so a top panel
+//! would otherwise end up with tiled windows drawn underneath it. We map dock
+//! windows ourselves rather than managing them, keep track of the space they
+//! ask for, and shrink the screens layouts are given to match.
+//!
+//! Struts are relative to the edges of the whole X screen rather than any one
+//! monitor, so a strut only takes space from the monitors it actually reaches.
+use penrose::{
+    core::State,
+    pure::geometry::Rect,
+    x::{atom::Atom, event::XEvent, property::Prop, ClientAttr, XConn, XConnExt},
+    Result, Xid,
+};
+use std::collections::HashMap;
+use tracing::{debug, info};
+
+const NET_WM_STRUT: &str = "_NET_WM_STRUT";
+const NET_WM_STRUT_PARTIAL: &str = "_NET_WM_STRUT_PARTIAL";
+const DOCK_TYPE: &str = "_NET_WM_WINDOW_TYPE_DOCK";
+
+/// Space reserved at each edge of the X screen, along with the range along that
+/// edge it covers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Strut {
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+    left_y: (u32, u32),
+    right_y: (u32, u32),
+    top_x: (u32, u32),
+    bottom_x: (u32, u32),
+}
+
+impl Strut {
+    /// Read a strut from the cardinals of `_NET_WM_STRUT_PARTIAL` (12 values)
+    /// or `_NET_WM_STRUT` (4 values, covering the whole length of each edge).
+    fn from_cardinals(values: &[u32]) -> Option<Self> {
+        let everywhere = (0, u32::MAX);
+        match values {
+            [left, right, top, bottom, ranges @ ..] if ranges.len() >= 8 => Some(Self {
+                left: *left,
+                right: *right,
+                top: *top,
+                bottom: *bottom,
+                left_y: (ranges[0], ranges[1]),
+                right_y: (ranges[2], ranges[3]),
+                top_x: (ranges[4], ranges[5]),
+                bottom_x: (ranges[6], ranges[7]),
+            }),
+            [left, right, top, bottom, ..] => Some(Self {
+                left: *left,
+                right: *right,
+                top: *top,
+                bottom: *bottom,
+                left_y: everywhere,
+                right_y: everywhere,
+                top_x: everywhere,
+                bottom_x: everywhere,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the inclusive `range` overlaps `start..start + len`.
+fn overlaps(range: (u32, u32), start: u32, len: u32) -> bool {
+    range.0 < start + len && range.1 >= start
+}
+
+/// `screens` with the space reserved by `struts` taken off.
+fn apply_struts(screens: &[Rect], struts: &[Strut]) -> Vec<Rect> {
+    let root_w = screens.iter().map(|r| r.x + r.w).max().unwrap_or(0);
+    let root_h = screens.iter().map(|r| r.y + r.h).max().unwrap_or(0);
+
+    screens
+        .iter()
+        .map(|r| {
+            let (mut left, mut right, mut top, mut bottom) = (0, 0, 0, 0);
+            for strut in struts {
+                if overlaps(strut.top_x, r.x, r.w) {
+                    top = top.max(strut.top.saturating_sub(r.y));
+                }
+                if overlaps(strut.bottom_x, r.x, r.w) {
+                    let edge = root_h.saturating_sub(strut.bottom);
+                    bottom = bottom.max((r.y + r.h).saturating_sub(edge));
+                }
+                if overlaps(strut.left_y, r.y, r.h) {
+                    left = left.max(strut.left.saturating_sub(r.x));
+                }
+                if overlaps(strut.right_y, r.y, r.h) {
+                    let edge = root_w.saturating_sub(strut.right);
+                    right = right.max((r.x + r.w).saturating_sub(edge));
+                }
+            }
+            let w = r.w.saturating_sub(left + right);
+            let h = r.h.saturating_sub(top + bottom);
+            Rect::new(r.x + left.min(r.w), r.y + top.min(r.h), w, h)
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct Struts {
+    docks: HashMap<Xid, Strut>,
+    /// The screens as penrose detected them, before making room for docks
+    full: Vec<Rect>,
+    /// What we last shrunk the screens to
+    applied: Vec<Rect>,
+}
+
+fn is_dock<X: XConn>(client: Xid, x: &X) -> bool {
+    match x
+        .get_prop(client, Atom::NetWmWindowType.as_ref())
+        .ok()
+        .flatten()
+    {
+        Some(Prop::Atom(types)) => types.iter().any(|t| t == DOCK_TYPE),
+        _ => false,
+    }
+}
+
+fn read_strut<X: XConn>(client: Xid, x: &X) -> Strut {
+    [NET_WM_STRUT_PARTIAL, NET_WM_STRUT]
+        .into_iter()
+        .find_map(|prop| match x.get_prop(client, prop).ok().flatten() {
+            Some(Prop::Cardinal(values)) => Strut::from_cardinals(&values),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn track_dock<X: XConn + 'static>(client: Xid, state: &mut State<X>, x: &X) -> Result<()> {
+    let strut = read_strut(client, x);
+    info!(?client, ?strut, "Reserving space for dock");
+    // So we hear about its strut changing
+    x.set_client_attributes(client, &[ClientAttr::ClientEventMask])?;
+    state
+        .extension_or_default::<Struts>()
+        .borrow_mut()
+        .docks
+        .insert(client, strut);
+
+    Ok(())
+}
+
+/// Startup hook picking up any docks that were already running.
+pub fn find_docks<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    for client in x.existing_clients()? {
+        if is_dock(client, x) {
+            track_dock(client, state, x)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Event hook mapping docks without managing them, and keeping their struts up
+/// to date.
+pub fn dock_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    match event {
+        XEvent::MapRequest(client) if is_dock(*client, x) => {
+            track_dock(*client, state, x)?;
+            x.map(*client)?;
+            x.refresh(state)?;
+            Ok(false)
+        }
+        XEvent::PropertyNotify(event)
+            if event.atom == NET_WM_STRUT || event.atom == NET_WM_STRUT_PARTIAL =>
+        {
+            let struts = state.extension_or_default::<Struts>();
+            let updated = match struts.borrow_mut().docks.get_mut(&event.id) {
+                Some(strut) => {
+                    *strut = read_strut(event.id, x);
+                    true
+                }
+                None => false,
+            };
+            if updated {
+                x.refresh(state)?;
+            }
+            Ok(true)
+        }
+        XEvent::Destroy(client) | XEvent::UnmapNotify(client) => {
+            let struts = state.extension_or_default::<Struts>();
+            let removed = struts.borrow_mut().docks.remove(client).is_some();
+            if removed {
+                debug!(?client, "Dock went away");
+                x.refresh(state)?;
+            }
+            Ok(true)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Refresh hook shrinking the screens to leave room for docks.
+pub fn reserve_struts<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let current = state
+        .client_set
+        .screens()
+        .map(|screen| screen.geometry())
+        .collect::<Vec<_>>();
+    let struts = state.extension_or_default::<Struts>();
+    let mut struts = struts.borrow_mut();
+    // Anything other than what we set means penrose has detected the screens
+    // again from scratch
+    if current != struts.applied {
+        struts.full = current.clone();
+    }
+    let docks = struts.docks.values().cloned().collect::<Vec<_>>();
+    let shrunk = apply_struts(&struts.full, &docks);
+    struts.applied = shrunk.clone();
+    if shrunk == current {
+        return Ok(());
+    }
+    std::mem::drop(struts);
+
+    debug!(?shrunk, "Making room for docks");
+    state.client_set.update_screens(shrunk)?;
+    x.refresh(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_struts_shrink_the_screens_they_reach() {
+        let left = Rect::new(0, 0, 1920, 1080);
+        let right = Rect::new(1920, 0, 1920, 1080);
+        // A 30px panel along the top of the left monitor only
+        let panel = Strut::from_cardinals(&[0, 0, 30, 0, 0, 0, 0, 0, 0, 1919, 0, 0]).unwrap();
+
+        assert_eq!(
+            apply_struts(&[left, right], &[panel]),
+            vec![Rect::new(0, 30, 1920, 1050), right]
+        );
+    }
+
+    #[test]
+    fn plain_struts_cover_the_whole_edge() {
+        let left = Rect::new(0, 0, 1920, 1080);
+        let right = Rect::new(1920, 0, 1920, 1080);
+        let panel = Strut::from_cardinals(&[0, 0, 30, 0]).unwrap();
+
+        assert_eq!(
+            apply_struts(&[left, right], &[panel]),
+            vec![
+                Rect::new(0, 30, 1920, 1050),
+                Rect::new(1920, 30, 1920, 1050)
+            ]
+        );
+        assert_eq!(apply_struts(&[left], &[]), vec![left]);
+    }
+}
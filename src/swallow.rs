@@ -0,0 +1,163 @@
+//! Swallowing terminals that launch GUI apps, like dwm's swallow patch.
+//!
+//! When a new window's process was started (however indirectly) from one of
+//! the configured terminals, the terminal is tucked away on a hidden tag and
+//! the new window takes its place. Once the window is destroyed, the terminal
+//! comes back to the tag it was taken from.
+use crate::{
+    default_layout_factory, is_floating_type,
+    queries::{client_pid, process_name},
+    sticky::unstick,
+    swallow_config, ManualPlacements,
+};
+use penrose::{
+    core::State,
+    x::{event::XEvent, XConn},
+    Result, Xid,
+};
+use std::{collections::HashMap, fs};
+use tracing::{debug, info};
+
+/// Where swallowed terminals wait to be restored.
+pub const SWALLOW_TAG: &str = "swallowed";
+
+#[derive(Debug, Default)]
+pub struct Swallowed {
+    /// Clients that swallowed a terminal, along with that terminal and the tag
+    /// it came from
+    pub clients: HashMap<Xid, (Xid, String)>,
+}
+
+/// The parent pid from the contents of `/proc/{pid}/stat`.
+fn parse_ppid(stat: &str) -> Option<u32> {
+    // The command name can contain anything, spaces and parentheses included,
+    // but it's the last thing in parentheses
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn parent_pid(pid: u32) -> Option<u32> {
+    fs::read_to_string(format!("/proc/{pid}/stat"))
+        .ok()
+        .and_then(|stat| parse_ppid(&stat))
+        .filter(|ppid| *ppid > 1)
+}
+
+/// Manage hook swallowing the terminal `client` was launched from, if any.
+pub fn swallow_terminal<X: XConn + 'static>(
+    client: Xid,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    let terminals = swallow_config(state).terminals;
+    if terminals.is_empty() || is_floating_type(client, x) {
+        return Ok(());
+    }
+    let pid = match client_pid(client, x)? {
+        Some(pid) => pid,
+        None => return Ok(()),
+    };
+    let is_terminal = |pid| process_name(pid).map_or(false, |name| terminals.contains(&name));
+    // A terminal opened from another terminal shouldn't replace it
+    if is_terminal(pid) {
+        return Ok(());
+    }
+
+    let candidates = state
+        .client_set
+        .clients()
+        .filter(|c| **c != client)
+        .filter_map(|c| Some((client_pid(*c, x).ok()??, *c)))
+        .filter(|(pid, _)| is_terminal(*pid))
+        .collect::<HashMap<_, _>>();
+    if candidates.is_empty() {
+        return Ok(());
+    }
+    let terminal = std::iter::successors(parent_pid(pid), |pid| parent_pid(*pid))
+        .find_map(|pid| candidates.get(&pid).cloned());
+    let (terminal, tag) =
+        match terminal.and_then(|t| Some((t, state.client_set.tag_for_client(&t)?.to_string()))) {
+            Some(found) if !crate::is_hidden_tag(&found.1) => found,
+            _ => return Ok(()),
+        };
+
+    info!(?client, ?terminal, "Swallowing terminal");
+    state.client_set.move_client_to_tag(&client, &tag);
+    hide_terminal(terminal, client, tag, state)?;
+    state.client_set.focus_client(&client);
+    // Keep it where the terminal was, wherever it'd usually go
+    state
+        .extension_or_default::<ManualPlacements>()
+        .borrow_mut()
+        .clients
+        .insert(client);
+
+    Ok(())
+}
+
+/// Tuck `terminal` away until `client` is destroyed, then bring it back to
+/// `tag`.
+pub fn hide_terminal<X: XConn + 'static>(
+    terminal: Xid,
+    client: Xid,
+    tag: String,
+    state: &mut State<X>,
+) -> Result<()> {
+    unstick(terminal, state);
+    if state.client_set.workspace(SWALLOW_TAG).is_none() {
+        state
+            .client_set
+            .add_workspace(SWALLOW_TAG, default_layout_factory())?;
+    }
+    state.client_set.move_client_to_tag(&terminal, SWALLOW_TAG);
+    state
+        .extension_or_default::<Swallowed>()
+        .borrow_mut()
+        .clients
+        .insert(client, (terminal, tag));
+
+    Ok(())
+}
+
+/// Event hook bringing a swallowed terminal back once the client that
+/// swallowed it is destroyed.
+pub fn unswallow_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    _: &X,
+) -> Result<bool> {
+    let client = match event {
+        XEvent::Destroy(client) => *client,
+        _ => return Ok(true),
+    };
+    let swallowed = state
+        .extension_or_default::<Swallowed>()
+        .borrow_mut()
+        .clients
+        .remove(&client);
+    let (terminal, tag) = match swallowed {
+        Some((terminal, tag)) if state.client_set.contains(&terminal) => (terminal, tag),
+        _ => return Ok(true),
+    };
+
+    debug!(?terminal, "Restoring swallowed terminal to {tag}");
+    let focused = state.client_set.current_client() == Some(&client);
+    state.client_set.move_client_to_tag(&terminal, &tag);
+    if focused {
+        state.client_set.focus_client(&terminal);
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parent_pids_are_read_past_odd_command_names() {
+        assert_eq!(parse_ppid("4242 (mpv) S 4100 4242 4100 34817"), Some(4100));
+        assert_eq!(parse_ppid("4242 (my (odd) app) R 17 4242 17 0"), Some(17));
+        assert_eq!(parse_ppid("garbage"), None);
+    }
+}
@@ -0,0 +1,73 @@
+//! Display names for tags. Tags themselves stay numbered, since placement and
+//! `backfill_gaps` rely on that, but they can be given a name to show in bars
+//! through `_NET_DESKTOP_NAMES` and the IPC `status` output.
+use penrose::{
+    core::State,
+    x::{property::Prop, XConn},
+    Result,
+};
+use std::collections::HashMap;
+
+const NET_DESKTOP_NAMES: &str = "_NET_DESKTOP_NAMES";
+
+#[derive(Debug, Default)]
+pub struct TagNames {
+    pub names: HashMap<String, String>,
+    /// What we last set `_NET_DESKTOP_NAMES` to
+    published: Vec<String>,
+}
+
+/// The name to show for `tag`.
+pub fn display_name<X: XConn + 'static>(state: &mut State<X>, tag: &str) -> String {
+    state
+        .extension_or_default::<TagNames>()
+        .borrow()
+        .names
+        .get(tag)
+        .cloned()
+        .unwrap_or_else(|| tag.to_string())
+}
+
+/// Name `tag`, or go back to showing its number if `name` is empty.
+pub fn rename<X: XConn + 'static>(state: &mut State<X>, tag: &str, name: &str) {
+    let names = state.extension_or_default::<TagNames>();
+    let mut names = names.borrow_mut();
+    if name.is_empty() || name == tag {
+        names.names.remove(tag);
+    } else {
+        names.names.insert(tag.to_string(), name.to_string());
+    }
+}
+
+/// Refresh hook keeping `_NET_DESKTOP_NAMES` up to date. This runs after the
+/// EWMH hooks so that the names aren't replaced with the bare tags, and only
+/// writes the property when it doesn't already say what we want.
+pub fn publish_desktop_names<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let tag_names = state.extension_or_default::<TagNames>();
+    let mut tag_names = tag_names.borrow_mut();
+    let desktop_names = state
+        .client_set
+        .ordered_tags()
+        .into_iter()
+        .map(|tag| tag_names.names.get(&tag).cloned().unwrap_or(tag))
+        .collect::<Vec<_>>();
+    if tag_names.names.is_empty() && desktop_names == tag_names.published {
+        return Ok(());
+    }
+    // The EWMH hooks may well have put the bare tags back, so check what's
+    // there rather than trusting what we last wrote
+    let current = x.get_prop(x.root(), NET_DESKTOP_NAMES)?;
+    if matches!(&current, Some(Prop::UTF8String(names)) if *names == desktop_names) {
+        tag_names.published = desktop_names;
+        return Ok(());
+    }
+
+    x.set_prop(
+        x.root(),
+        NET_DESKTOP_NAMES,
+        Prop::UTF8String(desktop_names.clone()),
+    )?;
+    tag_names.published = desktop_names;
+
+    Ok(())
+}
@@ -0,0 +1,118 @@
+//! Jumping between dialogs and the windows they belong to.
+//!
+//! Clients naming a parent through `WM_TRANSIENT_FOR` are recorded as they're
+//! managed, so `M-p` can take you from a dialog to its parent, or from a
+//! parent to the dialog it most recently opened.
+use crate::ForgetClient;
+use penrose::{
+    core::State,
+    x::{atom::Atom, property::Prop, XConn, XConnExt},
+    Result, Xid,
+};
+use tracing::debug;
+
+#[derive(Debug, Default)]
+pub struct Transients {
+    /// Transient children and their parents, oldest first
+    children: Vec<(Xid, Xid)>,
+}
+
+impl Transients {
+    /// The parent of `client` if it's transient, otherwise its newest
+    /// transient child.
+    pub fn related(&self, client: Xid) -> Option<Xid> {
+        let parent = self
+            .children
+            .iter()
+            .find(|(child, _)| *child == client)
+            .map(|(_, parent)| *parent);
+        parent.or_else(|| {
+            self.children
+                .iter()
+                .rev()
+                .find(|(_, parent)| *parent == client)
+                .map(|(child, _)| *child)
+        })
+    }
+}
+
+impl ForgetClient for Transients {
+    fn forget_client(&mut self, client: Xid) {
+        self.children
+            .retain(|(child, parent)| *child != client && *parent != client);
+    }
+}
+
+/// Manage hook recording who `client` is transient for, if anyone.
+pub fn record_transient<X: XConn + 'static>(
+    client: Xid,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<()> {
+    let parent = match x.get_prop(client, Atom::WmTransientFor.as_ref())? {
+        Some(Prop::Window(parents)) => match parents.first() {
+            Some(parent) if *parent != client => *parent,
+            _ => return Ok(()),
+        },
+        _ => return Ok(()),
+    };
+    debug!(?client, ?parent, "Client is transient");
+    state
+        .extension_or_default::<Transients>()
+        .borrow_mut()
+        .children
+        .push((client, parent));
+
+    Ok(())
+}
+
+/// Key handler focusing the parent of the focused dialog, or the newest dialog
+/// of the focused window.
+pub fn focus_transient_relative<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = match state.client_set.current_client().cloned() {
+        Some(client) => client,
+        None => return Ok(()),
+    };
+    let related = {
+        let transients = state.extension_or_default::<Transients>();
+        let mut transients = transients.borrow_mut();
+        transients.children.retain(|(child, parent)| {
+            state.client_set.contains(child) && state.client_set.contains(parent)
+        });
+        transients.related(client)
+    };
+    let related = match related {
+        Some(related) => related,
+        None => {
+            debug!(?client, "Client has no transient parent or children");
+            return Ok(());
+        }
+    };
+    if let Some(tag) = state.client_set.tag_for_client(&related).map(String::from) {
+        state.client_set.focus_tag(&tag);
+    }
+    state.client_set.focus_client(&related);
+
+    x.refresh(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transients_lead_to_parents_and_newest_children() {
+        let (parent, older, newer, other) =
+            (Xid::from(1), Xid::from(2), Xid::from(3), Xid::from(4));
+        let mut transients = Transients {
+            children: vec![(older, parent), (newer, parent)],
+        };
+
+        assert_eq!(transients.related(older), Some(parent));
+        assert_eq!(transients.related(parent), Some(newer));
+        assert_eq!(transients.related(other), None);
+
+        transients.forget_client(newer);
+        assert_eq!(transients.related(parent), Some(older));
+    }
+}
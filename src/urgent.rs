@@ -0,0 +1,145 @@
+//! Tracking clients that want our attention, either through the `WM_HINTS`
+//! urgency hint or `_NET_WM_STATE_DEMANDS_ATTENTION`.
+//!
+//! We don't steal focus for them (see [crate::activate]), they're just logged
+//! and reported over IPC so a bar can highlight their tag. `M-u` jumps to the
+//! one that most recently asked, and focusing a client clears its urgency.
+use crate::{
+    fullscreen::{apply_state_action, wm_state_request, WmStateRequest},
+    ForgetClient,
+};
+use penrose::{
+    core::State,
+    x::{
+        atom::Atom,
+        event::XEvent,
+        property::{Prop, WmHintsFlags},
+        XConn, XConnExt,
+    },
+    Result, Xid,
+};
+use std::collections::HashSet;
+use tracing::{debug, info};
+
+const DEMANDS_ATTENTION: &str = "_NET_WM_STATE_DEMANDS_ATTENTION";
+
+#[derive(Debug, Default)]
+pub struct UrgentClients {
+    pub clients: HashSet<Xid>,
+    /// The urgent clients, oldest first
+    order: Vec<Xid>,
+}
+
+impl ForgetClient for UrgentClients {
+    fn forget_client(&mut self, client: Xid) {
+        self.clients.remove(&client);
+        self.order.retain(|c| *c != client);
+    }
+}
+
+/// Clients currently asking for attention.
+pub fn urgent_clients<X: XConn + 'static>(state: &mut State<X>) -> HashSet<Xid> {
+    let urgent = state.extension_or_default::<UrgentClients>();
+    let mut urgent = urgent.borrow_mut();
+    urgent
+        .clients
+        .retain(|client| state.client_set.contains(client));
+    urgent.clients.clone()
+}
+
+fn has_urgency_hint<X: XConn>(client: Xid, x: &X) -> bool {
+    match x.get_prop(client, Atom::WmHints.as_ref()).ok().flatten() {
+        Some(Prop::WmHints(hints)) => hints.flags.contains(WmHintsFlags::URGENCY_HINT),
+        _ => false,
+    }
+}
+
+/// Mark `client` as wanting attention, or not, returning whether that changed
+/// anything.
+pub fn set_urgent<X: XConn + 'static>(client: Xid, urgent: bool, state: &mut State<X>) -> bool {
+    // The focused client already has our attention
+    let urgent = urgent && state.client_set.current_client() != Some(&client);
+    let extension = state.extension_or_default::<UrgentClients>();
+    let mut extension = extension.borrow_mut();
+    if urgent && extension.clients.insert(client) {
+        extension.order.push(client);
+        let tag = state.client_set.tag_for_client(&client).unwrap_or("?");
+        info!(?client, "Client on {tag} wants attention");
+        true
+    } else if !urgent && extension.clients.remove(&client) {
+        extension.order.retain(|c| *c != client);
+        true
+    } else {
+        false
+    }
+}
+
+/// Key handler focusing whichever client most recently became urgent,
+/// wherever it is.
+pub fn focus_urgent<X: XConn + 'static>(state: &mut State<X>, x: &X) -> Result<()> {
+    let client = {
+        let urgent = state.extension_or_default::<UrgentClients>();
+        let mut urgent = urgent.borrow_mut();
+        urgent
+            .order
+            .retain(|client| state.client_set.contains(client));
+        urgent.order.last().cloned()
+    };
+    let client = match client {
+        Some(client) => client,
+        None => {
+            debug!("No urgent clients to focus");
+            return Ok(());
+        }
+    };
+    if let Some(tag) = state.client_set.tag_for_client(&client).map(String::from) {
+        state.client_set.focus_tag(&tag);
+    }
+    state.client_set.focus_client(&client);
+    set_urgent(client, false, state);
+
+    x.refresh(state)
+}
+
+/// Event hook picking up changes to a client's urgency. Bars are kept up to date
+/// by the refresh that follows a change.
+pub fn urgency_listener<X: XConn + 'static>(
+    event: &XEvent,
+    state: &mut State<X>,
+    x: &X,
+) -> Result<bool> {
+    let changed = match event {
+        XEvent::PropertyNotify(event) if event.atom == Atom::WmHints.as_ref() => {
+            state.client_set.contains(&event.id)
+                && set_urgent(event.id, has_urgency_hint(event.id, x), state)
+        }
+        _ => {
+            let request = wm_state_request(event, state, x, DEMANDS_ATTENTION)?;
+            if let Some(WmStateRequest { client, action, .. }) = request {
+                let is_urgent = state
+                    .extension_or_default::<UrgentClients>()
+                    .borrow()
+                    .clients
+                    .contains(&client);
+                apply_state_action(action, is_urgent)
+                    .map_or(false, |urgent| set_urgent(client, urgent, state))
+            } else {
+                false
+            }
+        }
+    };
+    if changed {
+        x.refresh(state)?;
+    }
+
+    Ok(true)
+}
+
+/// Refresh hook clearing urgency once a client is focused.
+pub fn clear_focused_urgency<X: XConn + 'static>(state: &mut State<X>, _: &X) -> Result<()> {
+    if let Some(client) = state.client_set.current_client().cloned() {
+        set_urgent(client, false, state);
+    }
+
+    Ok(())
+}
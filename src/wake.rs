@@ -0,0 +1,47 @@
+//! Waking up the WM's event loop from another thread.
+//!
+//! penrose blocks waiting on X events, so anything running in the background
+//! sends a client message with its own atom to the root window over a separate
+//! connection, and an event hook watching for that atom picks things up from
+//! there on the WM's thread.
+use penrose::Result;
+use std::sync::Arc;
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{ClientMessageEvent, ConnectionExt, EventMask},
+    rust_connection::RustConnection,
+};
+
+#[derive(Debug, Clone)]
+pub struct Waker {
+    conn: Arc<RustConnection>,
+    root: u32,
+    atom: u32,
+}
+
+impl Waker {
+    /// Connect to the X server to send `atom_name` client messages.
+    pub fn new(atom_name: &str) -> Result<Self> {
+        let (conn, screen) = x11rb::connect(None)?;
+        let root = conn.setup().roots[screen].root;
+        let atom = conn.intern_atom(false, atom_name.as_bytes())?.reply()?.atom;
+
+        Ok(Self {
+            conn: Arc::new(conn),
+            root,
+            atom,
+        })
+    }
+
+    pub fn wake(&self) -> Result<()> {
+        self.conn.send_event(
+            false,
+            self.root,
+            EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+            ClientMessageEvent::new(32, self.root, self.atom, [0u32; 5]),
+        )?;
+        self.conn.flush()?;
+
+        Ok(())
+    }
+}